@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-use crate::profile::ProfileSchema;
+use crate::{
+    external::ExternalAccountFile, impersonate::ImpersonatedServiceAccountFile,
+    metadata::MetadataServer, profile::ProfileSchema,
+};
 
 #[derive(Clone, thiserror::Error, Debug)]
 pub enum Error {
@@ -31,6 +34,13 @@ pub enum CredentialsSchema {
     AuthorizedUser(AuthorizedUserFile),
     #[serde(rename = "service_account")]
     ServiceAccount(ServiceAccountFile),
+    #[serde(rename = "external_account")]
+    ExternalAccount(ExternalAccountFile),
+    #[serde(rename = "impersonated_service_account")]
+    ImpersonatedServiceAccount(ImpersonatedServiceAccountFile),
+    /// found by asking the metadata server rather than read from a file
+    #[serde(skip)]
+    Metadata(MetadataServer),
 }
 
 impl CredentialsSchema {
@@ -38,6 +48,11 @@ impl CredentialsSchema {
         match self {
             CredentialsSchema::AuthorizedUser(_) => None,
             CredentialsSchema::ServiceAccount(service) => service.project_id.as_deref(),
+            CredentialsSchema::Metadata(server) => server.project_id.as_deref(),
+            CredentialsSchema::ExternalAccount(_) => None,
+            CredentialsSchema::ImpersonatedServiceAccount(impersonated) => {
+                impersonated.source_credentials.project_id()
+            }
         }
     }
 
@@ -45,6 +60,9 @@ impl CredentialsSchema {
         match self {
             CredentialsSchema::AuthorizedUser(_) => None,
             CredentialsSchema::ServiceAccount(service) => Some(service.client_email.as_str()),
+            CredentialsSchema::Metadata(server) => server.email.as_deref(),
+            CredentialsSchema::ExternalAccount(external) => external.email(),
+            CredentialsSchema::ImpersonatedServiceAccount(impersonated) => impersonated.email(),
         }
     }
 
@@ -52,6 +70,9 @@ impl CredentialsSchema {
         match self {
             CredentialsSchema::AuthorizedUser(_) => "authorized_user",
             CredentialsSchema::ServiceAccount(_) => "service_account",
+            CredentialsSchema::Metadata(_) => "metadata_server",
+            CredentialsSchema::ExternalAccount(_) => "external_account",
+            CredentialsSchema::ImpersonatedServiceAccount(_) => "impersonated_service_account",
         }
     }
 
@@ -60,15 +81,52 @@ impl CredentialsSchema {
     }
 }
 
+/// looks up an environment variable, `std::env::var` unless tests replace it with `with_env`
+pub type Env = fn(&str) -> Option<String>;
+
+pub(crate) fn process_env(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
 pub struct GoogleCloudUserDirectory {
     root: PathBuf,
+    /// where the `CLOUDSDK_*` overrides are read from
+    pub(crate) env: Env,
 }
 
 impl GoogleCloudUserDirectory {
+    /// `CLOUDSDK_CONFIG` overrides the directory, like it does for gcloud
     pub fn new() -> Result<Self, Error> {
+        if let Ok(root) = std::env::var("CLOUDSDK_CONFIG") {
+            return Ok(Self::with_root(PathBuf::from(root)));
+        }
+
         let mut config = Self::get_user_config_directory()?;
         config.push("gcloud");
-        Ok(Self { root: config })
+        Ok(Self::with_root(config))
+    }
+
+    pub fn with_root(root: PathBuf) -> Self {
+        Self {
+            root,
+            env: process_env,
+        }
+    }
+
+    /// reads the `CLOUDSDK_*` overrides from `env` instead of the environment of the process
+    #[cfg(test)]
+    pub fn with_env(mut self, env: Env) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// `CLOUDSDK_ACTIVE_CONFIG_NAME`, then the `active_config` file written by `gcloud config configurations activate`
+    pub fn active_configuration(&self) -> String {
+        (self.env)("CLOUDSDK_ACTIVE_CONFIG_NAME")
+            .or_else(|| std::fs::read_to_string(self.root.join("active_config")).ok())
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .unwrap_or("default".to_string())
     }
 
     /// file @ `<user_config>/gcloud/application_default_credentials.json`
@@ -78,11 +136,11 @@ impl GoogleCloudUserDirectory {
         file
     }
 
-    /// file @ `<user_config>/gcloud/configurations/config_default`
-    pub fn get_config_default(&self) -> PathBuf {
+    /// file @ `<user_config>/gcloud/configurations/config_<name>`
+    pub fn get_configuration(&self, name: &str) -> PathBuf {
         let mut file = self.root.clone();
         file.push("configurations");
-        file.push("config_default");
+        file.push(format!("config_{}", name));
         file
     }
 
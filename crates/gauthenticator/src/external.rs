@@ -0,0 +1,290 @@
+//! workload identity federation, a token of another identity provider is exchanged for a google access token
+//!
+//! <https://cloud.google.com/iam/docs/workload-identity-federation>
+
+use crate::{
+    impersonate::{generate_access_token, generate_id_token, CLOUD_PLATFORM_SCOPE},
+    token::{handle_error, TokenError, TokenResponse, TokenResult},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf};
+
+const TOKEN_EXCHANGE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// a `external_account` file, as written by `gcloud iam workload-identity-pools create-cred-config`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExternalAccountFile {
+    /// the workload identity pool provider, e.g. `//iam.googleapis.com/projects/<number>/locations/global/workloadIdentityPools/<pool>/providers/<provider>`
+    pub audience: String,
+    /// e.g. `urn:ietf:params:oauth:token-type:jwt`
+    pub subject_token_type: String,
+    /// the STS endpoint, `https://sts.googleapis.com/v1/token`
+    pub token_url: String,
+    /// the federated token is exchanged again for a token of this service account when set
+    pub service_account_impersonation_url: Option<String>,
+    pub service_account_impersonation: Option<ImpersonationOptions>,
+    pub credential_source: CredentialSource,
+    pub quota_project_id: Option<String>,
+    /// only for workforce pools without impersonation
+    pub workforce_pool_user_project: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImpersonationOptions {
+    pub token_lifetime_seconds: Option<u64>,
+}
+
+/// where the token of the other identity provider is read from
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CredentialSource {
+    pub file: Option<PathBuf>,
+    pub url: Option<String>,
+    /// sent with the request to `url`
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub format: SubjectTokenFormat,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SubjectTokenFormat {
+    /// the whole file or response is the token
+    #[default]
+    Text,
+    /// the token is a field of a json object
+    Json { subject_token_field_name: String },
+}
+
+impl ExternalAccountFile {
+    /// the impersonated service account, read from its url
+    pub fn email(&self) -> Option<&str> {
+        let url = self.service_account_impersonation_url.as_deref()?;
+        let (_, account) = url.rsplit_once("/serviceAccounts/")?;
+        account.split(':').next()
+    }
+
+    /// the token of the other identity provider
+    pub fn subject_token(&self) -> TokenResult<String> {
+        let source = &self.credential_source;
+        let contents = match (&source.file, &source.url) {
+            (Some(path), _) => std::fs::read_to_string(path)?,
+            (None, Some(url)) => {
+                let mut request = ureq::get(url);
+                for (name, value) in &source.headers {
+                    request = request.set(name, value);
+                }
+                handle_error(request.call())?.into_string()?
+            }
+            (None, None) => {
+                return Err(TokenError::SubjectToken(
+                    "the credential source has neither a file nor a url".to_string(),
+                ))
+            }
+        };
+
+        match &source.format {
+            SubjectTokenFormat::Text => Ok(contents.trim().to_string()),
+            SubjectTokenFormat::Json {
+                subject_token_field_name,
+            } => {
+                let json: serde_json::Value = serde_json::from_str(&contents)
+                    .map_err(|e| TokenError::SubjectToken(e.to_string()))?;
+                json[subject_token_field_name]
+                    .as_str()
+                    .map(String::from)
+                    .ok_or_else(|| {
+                        TokenError::SubjectToken(format!(
+                            "no `{}` field in the credential source",
+                            subject_token_field_name
+                        ))
+                    })
+            }
+        }
+    }
+
+    /// a google-signed ID token of the impersonated service account, federated identities have none of their own
+    pub fn id_token(&self, audience: &str) -> TokenResult<String> {
+        let Some(url) = &self.service_account_impersonation_url else {
+            return Err(TokenError::Http(
+                "ID tokens need a `service_account_impersonation_url`".to_string(),
+            ));
+        };
+
+        let token = self.sts(CLOUD_PLATFORM_SCOPE)?;
+        generate_id_token(url, &token.access_token, &[], audience)
+    }
+
+    /// exchanges the subject token at the STS endpoint, then for a token of the service account if there is one
+    pub fn exchange(&self, scopes: &[String]) -> TokenResult<TokenResponse> {
+        let scopes = if scopes.is_empty() {
+            vec![CLOUD_PLATFORM_SCOPE.to_string()]
+        } else {
+            scopes.to_vec()
+        };
+
+        match &self.service_account_impersonation_url {
+            // the federated token only needs to be allowed to impersonate
+            Some(url) => {
+                let token = self.sts(CLOUD_PLATFORM_SCOPE)?;
+                self.impersonate(url, &token.access_token, &scopes)
+            }
+            None => self.sts(&scopes.join(" ")),
+        }
+    }
+
+    /// <https://cloud.google.com/iam/docs/reference/sts/rest/v1/TopLevel/token>
+    fn sts(&self, scope: &str) -> TokenResult<TokenResponse> {
+        let subject_token = self.subject_token()?;
+        let options = match (
+            &self.workforce_pool_user_project,
+            &self.service_account_impersonation_url,
+        ) {
+            (Some(project), None) => serde_json::json!({ "userProject": project }).to_string(),
+            _ => String::new(),
+        };
+
+        log::debug!(
+            "exchanging a {} at {}",
+            self.subject_token_type,
+            self.token_url
+        );
+        let mut form = vec![
+            ("grant_type", TOKEN_EXCHANGE),
+            ("audience", &self.audience),
+            ("scope", scope),
+            ("requested_token_type", ACCESS_TOKEN_TYPE),
+            ("subject_token", &subject_token),
+            ("subject_token_type", &self.subject_token_type),
+        ];
+        if !options.is_empty() {
+            form.push(("options", &options));
+        }
+        let response = handle_error(ureq::post(&self.token_url).send_form(&form))?;
+        Ok(response.into_json()?)
+    }
+
+    fn impersonate(&self, url: &str, token: &str, scopes: &[String]) -> TokenResult<TokenResponse> {
+        let lifetime = self
+            .service_account_impersonation
+            .as_ref()
+            .and_then(|i| i.token_lifetime_seconds)
+            .unwrap_or(3600);
+
+        log::debug!("impersonating {}", self.email().unwrap_or(url));
+        generate_access_token(url, token, &[], scopes, lifetime)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ExternalAccountFile;
+
+    fn sts() -> crate::fake::Server {
+        crate::fake::Server::start(|request| {
+            let path = request.path.as_str();
+            match path {
+                "/subject" if request.headers.get("metadata") == Some(&"True".to_string()) => {
+                    (200, r#"{"value": "eyJ.from.url"}"#.to_string())
+                }
+                "/v1/token" => (
+                    200,
+                    r#"{"access_token": "ya29.federated", "issued_token_type": "urn:ietf:params:oauth:token-type:access_token", "token_type": "Bearer", "expires_in": 3599}"#
+                        .to_string(),
+                ),
+                p if p.ends_with(":generateAccessToken") => (
+                    200,
+                    serde_json::json!({
+                        "accessToken": "ya29.impersonated",
+                        "expireTime": (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339()
+                    })
+                    .to_string(),
+                ),
+                _ => (404, "{}".to_string()),
+            }
+        })
+    }
+
+    fn config(server: &crate::fake::Server, source: serde_json::Value) -> ExternalAccountFile {
+        let json = serde_json::json!({
+            "type": "external_account",
+            "audience": "//iam.googleapis.com/projects/123/locations/global/workloadIdentityPools/ci/providers/github",
+            "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+            "token_url": format!("{}/v1/token", server.url),
+            "credential_source": source
+        });
+        let crate::CredentialsSchema::ExternalAccount(file) =
+            crate::CredentialsSchema::deserialize(&json.to_string()).unwrap()
+        else {
+            panic!("failed to deserialize into property structure");
+        };
+        file
+    }
+
+    #[test]
+    fn exchanges_file_sourced_token() {
+        let server = sts();
+        let path = std::env::temp_dir().join("gauthenticator-exchanges-file-sourced-token");
+        std::fs::write(&path, "eyJ.from.file\n").unwrap();
+
+        let file = config(&server, serde_json::json!({ "file": path }));
+        let token = file.exchange(&[]).unwrap();
+        assert_eq!(token.access_token, "ya29.federated");
+
+        let form = server.requests()[0].form();
+        assert_eq!(
+            form["grant_type"],
+            "urn:ietf:params:oauth:grant-type:token-exchange"
+        );
+        assert_eq!(form["subject_token"], "eyJ.from.file");
+        assert_eq!(
+            form["subject_token_type"],
+            "urn:ietf:params:oauth:token-type:jwt"
+        );
+        assert_eq!(form["audience"], file.audience);
+        assert_eq!(
+            form["scope"],
+            "https://www.googleapis.com/auth/cloud-platform"
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn exchanges_url_sourced_token_and_impersonates() {
+        let server = sts();
+        let mut file = config(
+            &server,
+            serde_json::json!({
+                "url": format!("{}/subject", server.url),
+                "headers": { "Metadata": "True" },
+                "format": { "type": "json", "subject_token_field_name": "value" }
+            }),
+        );
+        file.service_account_impersonation_url = Some(format!(
+            "{}/v1/projects/-/serviceAccounts/ci@test.iam.gserviceaccount.com:generateAccessToken",
+            server.url
+        ));
+        assert_eq!(file.email(), Some("ci@test.iam.gserviceaccount.com"));
+
+        let scopes = vec!["https://www.googleapis.com/auth/bigquery".to_string()];
+        let token = file.exchange(&scopes).unwrap();
+        assert_eq!(token.access_token, "ya29.impersonated");
+        assert!(!token.is_expired());
+
+        let requests = server.requests();
+        assert_eq!(requests[1].form()["subject_token"], "eyJ.from.url");
+        assert_eq!(
+            requests[1].form()["scope"],
+            "https://www.googleapis.com/auth/cloud-platform"
+        );
+        assert_eq!(
+            requests[2].headers["authorization"],
+            "Bearer ya29.federated"
+        );
+        let body: serde_json::Value = serde_json::from_str(&requests[2].body).unwrap();
+        assert_eq!(body["scope"], serde_json::json!(scopes));
+        assert_eq!(body["lifetime"], "3600s");
+    }
+}
@@ -0,0 +1,283 @@
+//! tokens of a service account minted with the credentials of another account, which needs no key of its own
+//!
+//! <https://cloud.google.com/iam/docs/service-account-impersonation>
+
+use crate::{
+    token::{handle_error, TokenError, TokenRequest, TokenResponse, TokenResult},
+    CredentialsSchema,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+const IAM_CREDENTIALS_URL: &str = "https://iamcredentials.googleapis.com/v1";
+/// the scope of the source token, and of the impersonated token when no scopes are asked for
+pub(crate) const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+/// an hour is the most allowed unless the organization policy extends it
+const DEFAULT_LIFETIME: u64 = 3600;
+
+/// a `impersonated_service_account` file, as written by `gcloud auth application-default login --impersonate-service-account`
+#[derive(Serialize, Deserialize)]
+pub struct ImpersonatedServiceAccountFile {
+    /// ends in `/serviceAccounts/<email>:generateAccessToken`
+    pub service_account_impersonation_url: String,
+    pub source_credentials: Box<CredentialsSchema>,
+    /// `projects/-/serviceAccounts/<email>`, each allowed to impersonate the next, the last the target
+    #[serde(default)]
+    pub delegates: Vec<String>,
+    pub quota_project_id: Option<String>,
+    /// seconds, up to 12 hours when the organization allows it
+    #[serde(skip)]
+    pub lifetime: Option<u64>,
+    /// the last token and the scopes it was minted for, reused until it expires but only in this process
+    #[serde(skip)]
+    cached: Mutex<Option<(Vec<String>, TokenResponse)>>,
+}
+
+/// the response of `generateAccessToken`, which differs from an OAuth2 token response
+#[derive(Debug, Deserialize)]
+struct GeneratedAccessToken {
+    #[serde(rename = "accessToken")]
+    access_token: String,
+    #[serde(rename = "expireTime")]
+    expire_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeneratedIdToken {
+    token: String,
+}
+
+/// <https://cloud.google.com/iam/docs/reference/credentials/rest/v1/projects.serviceAccounts/generateAccessToken>
+pub(crate) fn generate_access_token(
+    url: &str,
+    bearer: &str,
+    delegates: &[String],
+    scopes: &[String],
+    lifetime: u64,
+) -> TokenResult<TokenResponse> {
+    let result = ureq::post(url)
+        .set("Authorization", &format!("Bearer {}", bearer))
+        .send_json(serde_json::json!({
+            "delegates": delegates,
+            "scope": scopes,
+            "lifetime": format!("{}s", lifetime)
+        }));
+    let generated: GeneratedAccessToken = handle_error(result)?.into_json()?;
+
+    Ok(TokenResponse::new(
+        generated.access_token,
+        (generated.expire_time - Utc::now()).num_seconds(),
+    ))
+}
+
+/// `url` is the `generateAccessToken` url of the service account, which only differs in its method
+///
+/// <https://cloud.google.com/iam/docs/reference/credentials/rest/v1/projects.serviceAccounts/generateIdToken>
+pub(crate) fn generate_id_token(
+    url: &str,
+    bearer: &str,
+    delegates: &[String],
+    audience: &str,
+) -> TokenResult<String> {
+    let url = url
+        .strip_suffix(":generateAccessToken")
+        .map(|base| format!("{}:generateIdToken", base))
+        .ok_or_else(|| TokenError::Http(format!("cannot mint ID tokens with {}", url)))?;
+
+    let result = ureq::post(&url)
+        .set("Authorization", &format!("Bearer {}", bearer))
+        .send_json(serde_json::json!({
+            "delegates": delegates,
+            "audience": audience,
+            "includeEmail": true
+        }));
+    let generated: GeneratedIdToken = handle_error(result)?.into_json()?;
+    Ok(generated.token)
+}
+
+impl ImpersonatedServiceAccountFile {
+    /// impersonates `target` with the source credentials, through the chain of `delegates` emails
+    pub fn new(source: CredentialsSchema, target: &str, delegates: &[String]) -> Self {
+        Self {
+            service_account_impersonation_url: format!(
+                "{}/projects/-/serviceAccounts/{}:generateAccessToken",
+                IAM_CREDENTIALS_URL, target
+            ),
+            source_credentials: Box::new(source),
+            delegates: delegates
+                .iter()
+                .map(|email| format!("projects/-/serviceAccounts/{}", email))
+                .collect(),
+            quota_project_id: None,
+            lifetime: None,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// the impersonated service account, read from its url
+    pub fn email(&self) -> Option<&str> {
+        let (_, account) = self
+            .service_account_impersonation_url
+            .rsplit_once("/serviceAccounts/")?;
+        account.split(':').next()
+    }
+
+    /// a cloud-platform token of the source credentials, which must be allowed to create tokens for the target
+    fn source_token(&self) -> TokenResult<String> {
+        let request = TokenRequest::Exchange {
+            scopes: vec![CLOUD_PLATFORM_SCOPE.to_string()],
+            subject: None,
+        };
+        Ok(self
+            .source_credentials
+            .request_token(&request)?
+            .access_token)
+    }
+
+    /// an access token of the impersonated service account, reused while it has not expired
+    pub fn access_token(&self, scopes: &[String]) -> TokenResult<TokenResponse> {
+        let scopes = if scopes.is_empty() {
+            vec![CLOUD_PLATFORM_SCOPE.to_string()]
+        } else {
+            scopes.to_vec()
+        };
+
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, token)) = cached
+            .as_ref()
+            .filter(|(s, token)| *s == scopes && !token.is_expired())
+        {
+            log::debug!(
+                "reusing the impersonated token until {}",
+                token.expires_at()
+            );
+            return Ok(token.clone());
+        }
+
+        log::debug!(
+            "impersonating {}",
+            self.email()
+                .unwrap_or(&self.service_account_impersonation_url)
+        );
+        let token = generate_access_token(
+            &self.service_account_impersonation_url,
+            &self.source_token()?,
+            &self.delegates,
+            &scopes,
+            self.lifetime.unwrap_or(DEFAULT_LIFETIME),
+        )?;
+
+        *cached = Some((scopes, token.clone()));
+        Ok(token)
+    }
+
+    /// a google-signed ID token of the impersonated service account for `audience`
+    pub fn id_token(&self, audience: &str) -> TokenResult<String> {
+        generate_id_token(
+            &self.service_account_impersonation_url,
+            &self.source_token()?,
+            &self.delegates,
+            audience,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ImpersonatedServiceAccountFile;
+    use crate::{token::test::service_account_test, CredentialsSchema};
+
+    #[test]
+    fn impersonates_through_delegates_and_caches_tokens() {
+        let server = crate::fake::Server::start(|request| match request.path.as_str() {
+            "/token" => (
+                200,
+                r#"{"access_token": "ya29.source", "expires_in": 3599}"#.to_string(),
+            ),
+            p if p.ends_with(":generateAccessToken") => (
+                200,
+                serde_json::json!({
+                    "accessToken": "ya29.impersonated",
+                    "expireTime": (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339()
+                })
+                .to_string(),
+            ),
+            p if p.ends_with(":generateIdToken") => {
+                (200, r#"{"token": "eyJ.impersonated.id"}"#.to_string())
+            }
+            _ => (404, "{}".to_string()),
+        });
+
+        let mut source = service_account_test();
+        source.token_uri = Some(format!("{}/token", server.url));
+        let mut file = ImpersonatedServiceAccountFile::new(
+            CredentialsSchema::ServiceAccount(source),
+            "prod@test.iam.gserviceaccount.com",
+            &["hop@test.iam.gserviceaccount.com".to_string()],
+        );
+        file.service_account_impersonation_url = format!(
+            "{}/v1/projects/-/serviceAccounts/prod@test.iam.gserviceaccount.com:generateAccessToken",
+            server.url
+        );
+        file.lifetime = Some(600);
+        assert_eq!(file.email(), Some("prod@test.iam.gserviceaccount.com"));
+
+        let scopes = vec!["https://www.googleapis.com/auth/bigquery".to_string()];
+        let token = file.access_token(&scopes).unwrap();
+        assert_eq!(token.access_token, "ya29.impersonated");
+        assert_eq!(
+            file.access_token(&scopes).unwrap().access_token,
+            "ya29.impersonated"
+        );
+        assert_eq!(
+            file.id_token("https://example.run.app").unwrap(),
+            "eyJ.impersonated.id"
+        );
+
+        // the second access token came from the cache
+        let requests = server.requests();
+        let paths: Vec<&str> = requests.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(paths.len(), 4);
+        assert!(paths[1].ends_with(":generateAccessToken"));
+        assert!(paths[3].ends_with(":generateIdToken"));
+
+        assert_eq!(
+            crate::token::test::claims(&requests[0].form()["assertion"])["scope"],
+            "https://www.googleapis.com/auth/cloud-platform"
+        );
+        assert_eq!(requests[1].headers["authorization"], "Bearer ya29.source");
+        let body: serde_json::Value = serde_json::from_str(&requests[1].body).unwrap();
+        assert_eq!(
+            body["delegates"],
+            serde_json::json!(["projects/-/serviceAccounts/hop@test.iam.gserviceaccount.com"])
+        );
+        assert_eq!(body["scope"], serde_json::json!(scopes));
+        assert_eq!(body["lifetime"], "600s");
+
+        let body: serde_json::Value = serde_json::from_str(&requests[3].body).unwrap();
+        assert_eq!(body["audience"], "https://example.run.app");
+        assert_eq!(body["includeEmail"], true);
+    }
+
+    #[test]
+    fn deserializes_gcloud_file() {
+        let json = serde_json::json!({
+            "type": "impersonated_service_account",
+            "service_account_impersonation_url": "https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts/prod@test.iam.gserviceaccount.com:generateAccessToken",
+            "source_credentials": {
+                "type": "authorized_user",
+                "client_id": "12345.apps.googleusercontent.com",
+                "client_secret": "d-12345",
+                "refresh_token": "1//12345"
+            },
+            "delegates": []
+        });
+        let credentials = CredentialsSchema::deserialize(&json.to_string()).unwrap();
+        assert_eq!(credentials.kind(), "impersonated_service_account");
+        assert_eq!(
+            credentials.email(),
+            Some("prod@test.iam.gserviceaccount.com")
+        );
+    }
+}
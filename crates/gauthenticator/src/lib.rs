@@ -1,33 +1,44 @@
 mod credentials;
+mod external;
+#[cfg(test)]
+mod fake;
+mod impersonate;
+mod login;
+mod metadata;
+mod pkcs12;
 mod profile;
+mod provider;
 mod sign;
 mod token;
 
 /// exports
-pub use credentials::Error;
+pub use credentials::{CredentialsSchema, Error};
+pub use login::{application_default_credentials, save_credentials, Login};
+pub use metadata::MetadataServer;
+pub use pkcs12::PASSWORD as PKCS12_PASSWORD;
+pub use profile::ProfileSchema;
+pub use provider::{
+    ApplicationDefaultProvider, CredentialProvider, EnvProvider, FileProvider, GcloudProvider,
+    MetadataProvider, ProcessProvider, ProviderChain,
+};
+pub use token::{TokenRequest, TokenResponse, TOKEN_URI};
 
-use credentials::CredentialsSchema;
-use profile::{GoogleCloudConfigurationContext, ProfileSchema};
+use profile::GoogleCloudConfigurationContext;
 use std::path::Path;
 
 /// tries loading credentials from various well known locations
 pub fn from_env() -> FromEnv {
-    let mut context = GoogleCloudConfigurationContext::new();
-    let env = FromEnv {
-        google_application_credentials: from_environment_variable("GOOGLE_APPLICATION_CREDENTIALS"),
-        core_profile: context.as_mut().ok().and_then(|cxt| {
-            let profile = cxt.profiles.get("core").ok()?;
-            let path = cxt.directory.get_profile_adc(&profile);
-            let mut auth = crate::from_file(path);
-            auth.core_profile = Some(profile);
-            Some(auth)
-        }),
-        application_default: context
-            .as_ref()
-            .ok()
-            .map(|cxt| from_file(cxt.directory.get_application_default_credentials())),
-    };
-    env
+    from_env_configuration(None)
+}
+
+/// like `from_env`, with the named gcloud configuration rather than the active one
+pub fn from_env_configuration(configuration: Option<&str>) -> FromEnv {
+    FromEnv {
+        configuration: configuration.map(String::from),
+        gcloud: GoogleCloudConfigurationContext::new(configuration)
+            .and_then(|cxt| cxt.configuration())
+            .ok(),
+    }
 }
 
 pub fn from_environment_variable<S: AsRef<str>>(variable: S) -> Authentication {
@@ -42,6 +53,26 @@ pub fn from_environment_variable<S: AsRef<str>>(variable: S) -> Authentication {
     }
 }
 
+/// the service account attached to the GCE instance, Cloud Run service or GKE workload
+///
+/// off google cloud nothing answers, which takes up to a second to find out
+pub fn from_metadata_server() -> Authentication {
+    let server = MetadataServer::new();
+    let host = server.host().to_string();
+    let loading_from = format!("metadata:{}", host);
+
+    Authentication {
+        core_profile: None,
+        credentials: server
+            .detect()
+            .map(CredentialsSchema::Metadata)
+            .ok_or_else(|| {
+                Error::FailedToLoad(format!("{} because no metadata server answered", host))
+            }),
+        loading_from,
+    }
+}
+
 pub fn from_file<P: AsRef<Path>>(path: P) -> Authentication {
     let path = path.as_ref();
     let result = std::fs::read_to_string(path)
@@ -55,6 +86,23 @@ pub fn from_file<P: AsRef<Path>>(path: P) -> Authentication {
     }
 }
 
+/// a `.p12` key of `client_email` protected by google's `notasecret`, which predates json keys
+pub fn from_pkcs12_file<P: AsRef<Path>>(path: P, client_email: &str) -> Authentication {
+    let path = path.as_ref();
+    let result = std::fs::read(path)
+        .and_then(|p12| {
+            credentials::ServiceAccountFile::from_pkcs12(&p12, PKCS12_PASSWORD, client_email)
+        })
+        .map(CredentialsSchema::ServiceAccount)
+        .map_err(|e| Error::FailedToLoad(format!("{} because {}", path.display(), e)));
+
+    Authentication {
+        core_profile: None,
+        credentials: result,
+        loading_from: format!("{}", path.display()),
+    }
+}
+
 pub struct Authentication {
     loading_from: String,
     credentials: Result<CredentialsSchema, Error>,
@@ -62,12 +110,31 @@ pub struct Authentication {
 }
 
 impl Authentication {
+    /// `loading_from` says where the credentials came from, or were looked for
+    pub fn new(
+        loading_from: impl Into<String>,
+        credentials: Result<CredentialsSchema, Error>,
+    ) -> Self {
+        Self {
+            loading_from: loading_from.into(),
+            credentials,
+            core_profile: None,
+        }
+    }
+
+    pub fn loading_from(&self) -> &str {
+        &self.loading_from
+    }
+
     pub fn project_id(&self) -> Option<&str> {
         self.credentials
             .as_ref()
             .ok()
             .and_then(|c| c.project_id())
-            .or(self.core_profile.as_ref().map(|p| p.project.as_str()))
+            .or(self
+                .core_profile
+                .as_ref()
+                .and_then(|p| p.project.as_deref()))
     }
 
     pub fn token(&self, audience: Option<String>) -> Result<String, Error> {
@@ -82,6 +149,38 @@ impl Authentication {
         Ok(token)
     }
 
+    /// a token chosen by `request`, with when it expires
+    pub fn request_token(&self, request: &TokenRequest) -> Result<TokenResponse, Error> {
+        let credentials = self.credentials.as_ref().map_err(|e| e.clone())?;
+
+        credentials
+            .request_token(request)
+            .map_err(|e| Error::TokenFailed(e.to_string()))
+    }
+
+    /// acts as the `target` service account, the credentials only need `roles/iam.serviceAccountTokenCreator` on it
+    ///
+    /// `delegates` are the emails of the service accounts in between, each allowed to impersonate the next
+    pub fn impersonate(
+        self,
+        target: &str,
+        delegates: &[String],
+        lifetime: Option<u64>,
+    ) -> Authentication {
+        let credentials = self.credentials.map(|source| {
+            let mut impersonated =
+                impersonate::ImpersonatedServiceAccountFile::new(source, target, delegates);
+            impersonated.lifetime = lifetime;
+            CredentialsSchema::ImpersonatedServiceAccount(impersonated)
+        });
+
+        Authentication {
+            loading_from: format!("{} impersonating {}", self.loading_from, target),
+            credentials,
+            core_profile: self.core_profile,
+        }
+    }
+
     pub fn message(&self) -> String {
         let mut message = String::new();
 
@@ -114,69 +213,76 @@ impl Authentication {
 }
 
 pub struct FromEnv {
-    google_application_credentials: Authentication,
-    core_profile: Option<Authentication>,
-    application_default: Option<Authentication>,
+    configuration: Option<String>,
+    gcloud: Option<ProfileSchema>,
 }
 
 impl FromEnv {
+    /// every provider of the chain, why each was skipped and which one is used
     pub fn print(&self) {
         println!(
-            "various well known locations have been checked for valid credentials:\n{}\n{}\n{}",
-            &self.google_application_credentials.message(),
-            &self
-                .core_profile
+            "various well known locations have been checked for valid credentials:\n{}",
+            self.gcloud
                 .as_ref()
-                .map(|c| c.message())
-                .as_deref()
-                .unwrap_or("N/A"),
-            &self
-                .application_default
-                .as_ref()
-                .map(|c| c.message())
-                .as_deref()
-                .unwrap_or("N/A"),
+                .map(|c| format!(
+                    "\ngcloud configuration `{}`\nproject:\t{}\nregion:\t{}\nquota project:\t{}",
+                    c.name,
+                    c.project.as_deref().unwrap_or("N/A"),
+                    c.region.as_deref().unwrap_or("N/A"),
+                    c.quota_project.as_deref().unwrap_or("N/A")
+                ))
+                .unwrap_or("\ngcloud configuration N/A".to_string()),
         );
-    }
 
-    pub fn authentication(self) -> Option<Authentication> {
-        if self.google_application_credentials.credentials.is_ok() {
-            return Some(self.google_application_credentials);
+        let attempts = self.chain().attempts();
+        for attempt in &attempts {
+            println!("{}", attempt.message());
         }
 
-        if self
-            .core_profile
-            .as_ref()
-            .ok_or(false)
-            .map(|p| p.credentials.is_ok())
-            .unwrap_or(false)
-        {
-            return self.core_profile;
+        match attempts.iter().find(|a| a.credentials.is_ok()) {
+            Some(used) => println!("\nusing {}", used.loading_from),
+            None => println!("\nno credentials found"),
         }
+    }
 
-        if self
-            .application_default
-            .as_ref()
-            .ok_or(false)
-            .map(|p| p.credentials.is_ok())
-            .unwrap_or(false)
-        {
-            return self.application_default;
-        }
+    /// the active gcloud configuration, or the one named in `from_env_configuration`
+    pub fn gcloud_configuration(&self) -> Option<&ProfileSchema> {
+        self.gcloud.as_ref()
+    }
+
+    /// the providers `authentication` tries, in order
+    pub fn chain(&self) -> ProviderChain {
+        ProviderChain::with_configuration(self.configuration.as_deref())
+    }
+
+    /// credentials from `GOOGLE_APPLICATION_CREDENTIALS`
+    pub fn google_application_credentials(self) -> Authentication {
+        EnvProvider("GOOGLE_APPLICATION_CREDENTIALS".to_string()).provide()
+    }
+
+    /// credentials of the account in the active gcloud configuration
+    pub fn core_profile(self) -> Option<Authentication> {
+        self.gcloud.as_ref()?;
+        Some(
+            GcloudProvider {
+                configuration: self.configuration,
+            }
+            .provide(),
+        )
+    }
 
-        None
+    /// credentials created by `gcloud auth application-default login`
+    pub fn application_default(self) -> Option<Authentication> {
+        Some(ApplicationDefaultProvider.provide())
+    }
+
+    pub fn authentication(self) -> Option<Authentication> {
+        self.chain().authentication()
     }
 
     pub fn credentials(self) -> Result<CredentialsSchema, Error> {
-        self.google_application_credentials
-            .credentials
-            .or(self
-                .core_profile
-                .ok_or(Error::NotFound)
-                .and_then(|c| c.credentials))
-            .or(self
-                .application_default
-                .ok_or(Error::NotFound)
-                .and_then(|c| c.credentials))
+        self.authentication()
+            .map(|auth| auth.credentials)
+            .unwrap_or(Err(Error::NotFound))
     }
 }
@@ -0,0 +1,372 @@
+//! signs a user in with the installed app flow, so credentials do not need the gcloud sdk
+//!
+//! <https://developers.google.com/identity/protocols/oauth2/native-app>
+
+use crate::{
+    credentials::{AuthorizedUserFile, GoogleCloudUserDirectory},
+    token::{handle_error, TokenError, TokenResult, TOKEN_URI},
+    CredentialsSchema, Error,
+};
+use base64::{engine::general_purpose, Engine as _};
+use ring::rand::SecureRandom;
+use serde::Deserialize;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+pub const AUTH_URI: &str = "https://accounts.google.com/o/oauth2/auth";
+/// how long `wait_for_redirect` waits for the browser
+pub const REDIRECT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const DEFAULT_SCOPES: [&str; 3] = [
+    "openid",
+    "https://www.googleapis.com/auth/userinfo.email",
+    "https://www.googleapis.com/auth/cloud-platform",
+];
+
+/// a `client_secret.json` downloaded for a "Desktop app" OAuth client
+#[derive(Debug, Deserialize)]
+struct ClientSecrets {
+    installed: InstalledClient,
+}
+
+#[derive(Debug, Deserialize)]
+struct InstalledClient {
+    client_id: String,
+    client_secret: String,
+    auth_uri: Option<String>,
+    token_uri: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthorizationResponse {
+    refresh_token: Option<String>,
+}
+
+pub struct Login {
+    pub client_id: String,
+    pub client_secret: String,
+    pub auth_uri: String,
+    pub token_uri: String,
+    pub scopes: Vec<String>,
+    /// the PKCE secret, only its hash is sent with the authorization request
+    verifier: String,
+    state: String,
+}
+
+impl Login {
+    /// signs in with your own OAuth client, for `openid`, `email` and `cloud-platform`
+    ///
+    /// there is no default client, the clients of other tools such as gcloud are not theirs to share
+    pub fn with_client(client_id: &str, client_secret: &str) -> Self {
+        Self {
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            auth_uri: AUTH_URI.to_string(),
+            token_uri: TOKEN_URI.to_string(),
+            scopes: DEFAULT_SCOPES.iter().map(|s| s.to_string()).collect(),
+            verifier: random(32),
+            state: random(16),
+        }
+    }
+
+    /// the client of a `client_secret.json`
+    pub fn from_client_secrets<P: AsRef<Path>>(path: P) -> TokenResult<Self> {
+        let secrets: ClientSecrets = serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| TokenError::Http(format!("invalid client secrets: {}", e)))?;
+        let client = secrets.installed;
+
+        let mut login = Self::with_client(&client.client_id, &client.client_secret);
+        login.auth_uri = client.auth_uri.unwrap_or(login.auth_uri);
+        login.token_uri = client.token_uri.unwrap_or(login.token_uri);
+        Ok(login)
+    }
+
+    /// the page to sign in on, which redirects to `redirect_uri` with the code
+    pub fn authorization_url(&self, redirect_uri: &str) -> String {
+        let challenge = general_purpose::URL_SAFE_NO_PAD.encode(ring::digest::digest(
+            &ring::digest::SHA256,
+            self.verifier.as_bytes(),
+        ));
+
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("response_type", "code")
+            .append_pair("scope", &self.scopes.join(" "))
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256")
+            .append_pair("state", &self.state)
+            // a refresh token is only returned for offline access, and again only when consent is asked for
+            .append_pair("access_type", "offline")
+            .append_pair("prompt", "consent")
+            .finish();
+        format!("{}?{}", self.auth_uri, query)
+    }
+
+    /// the code of a redirect url pasted by hand, e.g. from a browser on another machine
+    ///
+    /// the redirect page fails to load there, but its url in the address bar carries the code
+    pub fn code_from_redirect(&self, redirect: &str) -> TokenResult<String> {
+        let redirect = redirect.trim();
+        let Some((_, query)) = redirect.split_once('?') else {
+            // just the code was pasted
+            return Ok(redirect.to_string());
+        };
+
+        if !self.is_own_redirect(query) {
+            return Err(TokenError::Http(
+                "the redirect does not belong to this sign in".to_string(),
+            ));
+        }
+
+        let mut code = None;
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            match key.as_ref() {
+                "code" => code = Some(value.to_string()),
+                "error" => return Err(TokenError::Http(format!("sign in failed: {}", value))),
+                _ => {}
+            }
+        }
+        code.ok_or_else(|| TokenError::Http("the redirect has no code".to_string()))
+    }
+
+    /// whether the query of a redirect carries the `state` of this sign in
+    fn is_own_redirect(&self, query: &str) -> bool {
+        url::form_urlencoded::parse(query.as_bytes())
+            .any(|(key, value)| key == "state" && value == self.state.as_str())
+    }
+
+    /// waits for the browser to be redirected to the loopback address and returns the code
+    ///
+    /// requests without the `state` of this sign in, e.g. forged by another local process, are
+    /// answered with a 400 and ignored, and the wait gives up after `REDIRECT_TIMEOUT`
+    pub fn wait_for_redirect(&self, listener: &TcpListener) -> TokenResult<String> {
+        let deadline = Instant::now() + REDIRECT_TIMEOUT;
+        listener.set_nonblocking(true)?;
+
+        loop {
+            let mut stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        return Err(TokenError::Http(
+                            "timed out waiting for the browser to sign in".to_string(),
+                        ));
+                    }
+                    std::thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+            let mut line = String::new();
+            if BufReader::new(&mut stream).read_line(&mut line).is_err() {
+                continue;
+            }
+
+            // `GET /?code=...&state=... HTTP/1.1`, browsers also ask for a favicon
+            let path = line.split_whitespace().nth(1).unwrap_or_default();
+            if !path.contains("code=") && !path.contains("error=") {
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+                );
+                continue;
+            }
+
+            let query = path.split_once('?').map(|(_, q)| q).unwrap_or_default();
+            if !self.is_own_redirect(query) {
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n"
+                );
+                continue;
+            }
+
+            let code = self.code_from_redirect(path);
+            let body = match &code {
+                Ok(_) => "signed in, you can close this tab and return to the terminal",
+                Err(_) => "sign in failed, see the terminal for details",
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            return code;
+        }
+    }
+
+    /// exchanges the code for a refresh token
+    pub fn exchange(&self, code: &str, redirect_uri: &str) -> TokenResult<CredentialsSchema> {
+        let result = ureq::post(&self.token_uri).send_form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("client_id", &self.client_id),
+            ("client_secret", &self.client_secret),
+            ("redirect_uri", redirect_uri),
+            ("code_verifier", &self.verifier),
+        ]);
+        let response: AuthorizationResponse = handle_error(result)?.into_json()?;
+
+        let Some(refresh_token) = response.refresh_token else {
+            return Err(TokenError::Http(
+                "no refresh token was returned, revoke the previous consent and sign in again"
+                    .to_string(),
+            ));
+        };
+
+        Ok(CredentialsSchema::AuthorizedUser(AuthorizedUserFile {
+            client_id: self.client_id.clone(),
+            client_secret: self.client_secret.clone(),
+            refresh_token,
+        }))
+    }
+}
+
+/// where `gcloud auth application-default login` writes, and `from_env` reads, the credentials of the user
+pub fn application_default_credentials() -> Result<PathBuf, Error> {
+    Ok(GoogleCloudUserDirectory::new()?.get_application_default_credentials())
+}
+
+/// writes the credentials as json, readable only by the user on unix
+pub fn save_credentials<P: AsRef<Path>>(
+    credentials: &CredentialsSchema,
+    path: P,
+) -> std::io::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(serde_json::to_string_pretty(credentials)?.as_bytes())
+}
+
+/// url-safe characters from `bytes` random bytes
+fn random(bytes: usize) -> String {
+    let mut buffer = vec![0; bytes];
+    ring::rand::SystemRandom::new()
+        .fill(&mut buffer)
+        .expect("the system random number generator is unavailable");
+    general_purpose::URL_SAFE_NO_PAD.encode(buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{save_credentials, Login};
+    use crate::CredentialsSchema;
+    use std::{io::Read, net::TcpListener};
+
+    fn query(url: &str) -> std::collections::HashMap<String, String> {
+        let (_, query) = url.split_once('?').unwrap();
+        url::form_urlencoded::parse(query.as_bytes())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn signs_in_through_loopback_redirect() {
+        let server = crate::fake::Server::start(|_| {
+            (
+                200,
+                r#"{"access_token": "ya29.user", "refresh_token": "1//refresh", "expires_in": 3599}"#
+                    .to_string(),
+            )
+        });
+        let mut login = Login::with_client("client.apps.googleusercontent.com", "secret");
+        login.token_uri = format!("{}/token", server.url);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let redirect_uri = format!("http://{}/", listener.local_addr().unwrap());
+        let url = login.authorization_url(&redirect_uri);
+        let params = query(&url);
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert_eq!(params["redirect_uri"], redirect_uri);
+        assert!(params["scope"].contains("https://www.googleapis.com/auth/cloud-platform"));
+
+        // another local process, then the browser after the user consented
+        let forged = format!("{}?state=forged&error=access_denied", redirect_uri);
+        let redirect = format!("{}?state={}&code=4%2Fcode", redirect_uri, params["state"]);
+        let browser = std::thread::spawn(move || {
+            let get = |url: &str| {
+                let mut response = String::new();
+                let address = url.trim_start_matches("http://");
+                let (host, path) = address.split_once('/').unwrap();
+                let mut stream = std::net::TcpStream::connect(host).unwrap();
+                use std::io::Write;
+                write!(stream, "GET /{} HTTP/1.1\r\nHost: {}\r\n\r\n", path, host).unwrap();
+                stream.read_to_string(&mut response).unwrap();
+                response
+            };
+            (get(&forged), get(&redirect))
+        });
+
+        let code = login.wait_for_redirect(&listener).unwrap();
+        assert_eq!(code, "4/code");
+        let (forged, signed_in) = browser.join().unwrap();
+        assert!(forged.starts_with("HTTP/1.1 400"));
+        assert!(signed_in.contains("signed in"));
+
+        let credentials = login.exchange(&code, &redirect_uri).unwrap();
+        let form = server.requests()[0].form();
+        assert_eq!(form["grant_type"], "authorization_code");
+        assert_eq!(form["code"], "4/code");
+        assert_eq!(form["redirect_uri"], redirect_uri);
+
+        // the challenge sent with the authorization request is the hash of the verifier sent now
+        use base64::{engine::general_purpose, Engine as _};
+        let challenge = general_purpose::URL_SAFE_NO_PAD.encode(ring::digest::digest(
+            &ring::digest::SHA256,
+            form["code_verifier"].as_bytes(),
+        ));
+        assert_eq!(challenge, params["code_challenge"]);
+
+        let path = std::env::temp_dir().join("gauthenticator-signs-in/adc.json");
+        save_credentials(&credentials, &path).unwrap();
+        let saved = crate::from_file(&path);
+        let CredentialsSchema::AuthorizedUser(user) = saved.credentials.unwrap() else {
+            panic!("expected authorized user credentials");
+        };
+        assert_eq!(user.refresh_token, "1//refresh");
+        assert_eq!(user.client_id, "client.apps.googleusercontent.com");
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reads_pasted_redirects() {
+        let login = Login::with_client("client", "secret");
+        let url = login.authorization_url("http://127.0.0.1:8085/");
+        let state = &query(&url)["state"];
+
+        let pasted = format!(
+            "http://127.0.0.1:8085/?state={}&code=4/abc&scope=openid",
+            state
+        );
+        assert_eq!(login.code_from_redirect(&pasted).unwrap(), "4/abc");
+        assert_eq!(login.code_from_redirect(" 4/abc\n").unwrap(), "4/abc");
+        assert!(login
+            .code_from_redirect("http://127.0.0.1:8085/?state=forged&code=4/abc")
+            .is_err());
+        assert!(login
+            .code_from_redirect(&format!(
+                "http://127.0.0.1:8085/?state={}&error=access_denied",
+                state
+            ))
+            .is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! credentials of the service account attached to a GCE instance, Cloud Run service or GKE workload
+//!
+//! <https://cloud.google.com/compute/docs/metadata/overview>
+
+use crate::token::{handle_error, TokenError, TokenResponse, TokenResult};
+use std::time::Duration;
+
+/// `GCE_METADATA_HOST` overrides it, e.g. with `metadata.google.internal`
+const DEFAULT_HOST: &str = "169.254.169.254";
+/// off google cloud nothing answers, so detection gives up quickly
+const DETECT_TIMEOUT: Duration = Duration::from_millis(500);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct MetadataServer {
+    /// e.g. `http://169.254.169.254`
+    base: String,
+    /// filled in once the server is detected
+    pub project_id: Option<String>,
+    pub email: Option<String>,
+}
+
+impl MetadataServer {
+    pub fn new() -> Self {
+        let host = std::env::var("GCE_METADATA_HOST").unwrap_or(DEFAULT_HOST.to_string());
+        Self::with_host(&host)
+    }
+
+    /// `host` may include a port, e.g. `127.0.0.1:8080`
+    pub fn with_host(host: &str) -> Self {
+        Self {
+            base: format!("http://{}/computeMetadata/v1", host),
+            project_id: None,
+            email: None,
+        }
+    }
+
+    pub fn host(&self) -> &str {
+        self.base
+            .trim_start_matches("http://")
+            .trim_end_matches("/computeMetadata/v1")
+    }
+
+    /// whether a metadata server answers, reading the project and account email when it does
+    pub fn detect(mut self) -> Option<Self> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(DETECT_TIMEOUT)
+            .timeout(DETECT_TIMEOUT * 2)
+            .build();
+
+        // anything else listening on the address would not set the flavor
+        let response = agent
+            .get(&format!("{}/", self.base))
+            .set("Metadata-Flavor", "Google")
+            .call()
+            .ok()?;
+        if response.header("Metadata-Flavor") != Some("Google") {
+            return None;
+        }
+
+        self.project_id = self.get("project/project-id").ok();
+        self.email = self.get("instance/service-accounts/default/email").ok();
+        Some(self)
+    }
+
+    fn get(&self, path: &str) -> TokenResult<String> {
+        self.request(path, &[])?
+            .into_string()
+            .map_err(TokenError::from)
+    }
+
+    fn request(&self, path: &str, query: &[(&str, &str)]) -> TokenResult<ureq::Response> {
+        let mut request = ureq::AgentBuilder::new()
+            .timeout(TIMEOUT)
+            .build()
+            .get(&format!("{}/{}", self.base, path))
+            .set("Metadata-Flavor", "Google");
+
+        for (name, value) in query {
+            request = request.query(name, value);
+        }
+
+        handle_error(request.call())
+    }
+
+    /// an access token of the attached service account, `scopes` are only honoured on GKE and Cloud Run
+    pub fn token(&self, scopes: &[String]) -> TokenResult<TokenResponse> {
+        let scopes = scopes.join(",");
+        let query: &[(&str, &str)] = if scopes.is_empty() {
+            &[]
+        } else {
+            &[("scopes", &scopes)]
+        };
+
+        let response = self.request("instance/service-accounts/default/token", query)?;
+        Ok(response.into_json()?)
+    }
+
+    /// a google-signed ID token for `audience`, e.g. the url of a Cloud Run service
+    pub fn id_token(&self, audience: &str) -> TokenResult<String> {
+        let response = self.request(
+            "instance/service-accounts/default/identity",
+            &[("audience", audience), ("format", "full")],
+        )?;
+        Ok(response.into_string()?)
+    }
+}
+
+impl Default for MetadataServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MetadataServer;
+
+    fn server() -> crate::fake::Server {
+        crate::fake::Server::start(|request| {
+            if request.headers.get("metadata-flavor").map(String::as_str) != Some("Google") {
+                return (403, "missing Metadata-Flavor".to_string());
+            }
+
+            let path = request.path.trim_start_matches("/computeMetadata/v1");
+            let body = match path.split_once('?').map_or(path, |(path, _)| path) {
+                "/" => "computeMetadata/",
+                "/project/project-id" => "test-project",
+                "/instance/service-accounts/default/email" => {
+                    "vm@test-project.iam.gserviceaccount.com"
+                }
+                "/instance/service-accounts/default/token" => {
+                    r#"{"access_token": "ya29.metadata", "expires_in": 3599, "token_type": "Bearer"}"#
+                }
+                "/instance/service-accounts/default/identity" => "eyJ.id.token",
+                _ => return (404, "not found".to_string()),
+            };
+            (200, body.to_string())
+        })
+    }
+
+    #[test]
+    fn reads_metadata_server() {
+        let server = server();
+        let metadata = MetadataServer::with_host(server.url.trim_start_matches("http://"))
+            .detect()
+            .unwrap();
+
+        assert_eq!(metadata.project_id.as_deref(), Some("test-project"));
+        assert_eq!(
+            metadata.email.as_deref(),
+            Some("vm@test-project.iam.gserviceaccount.com")
+        );
+
+        let token = metadata.token(&["a".to_string(), "b".to_string()]).unwrap();
+        assert_eq!(token.access_token, "ya29.metadata");
+        assert_eq!(
+            metadata.id_token("https://example.run.app").unwrap(),
+            "eyJ.id.token"
+        );
+
+        let requests = server.requests();
+        assert!(requests
+            .iter()
+            .any(|r| r.path.ends_with("/token?scopes=a%2Cb")));
+        assert!(requests
+            .iter()
+            .any(|r| r.path.contains("audience=https%3A%2F%2Fexample.run.app")));
+    }
+
+    #[test]
+    fn detection_fails_quickly_without_a_server() {
+        // nothing listens on the discard port of localhost
+        let started = std::time::Instant::now();
+        assert!(MetadataServer::with_host("127.0.0.1:9").detect().is_none());
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    }
+}
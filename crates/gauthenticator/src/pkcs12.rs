@@ -0,0 +1,31 @@
+//! the private key of a PKCS#12 (`.p12`) file, as google issued for service accounts before json keys
+//!
+//! <https://www.rfc-editor.org/rfc/rfc7292>
+
+use p12_keystore::{error::Error, KeyStore};
+use std::io;
+
+/// the password of every `.p12` key google issued
+pub const PASSWORD: &str = "notasecret";
+
+fn invalid(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid PKCS#12 file: {}", message),
+    )
+}
+
+/// the PKCS#8 DER encoded private key of the file, which must be protected by `password`
+pub fn private_key(p12: &[u8], password: &str) -> io::Result<Vec<u8>> {
+    let keystore = KeyStore::from_pkcs12(p12, password).map_err(|e| match e {
+        Error::MacError(_) => {
+            io::Error::new(io::ErrorKind::InvalidInput, "the PKCS#12 password is wrong")
+        }
+        e => invalid(e),
+    })?;
+
+    let (_, chain) = keystore
+        .private_key_chain()
+        .ok_or_else(|| invalid("it has no private key"))?;
+    Ok(chain.key().to_vec())
+}
@@ -1,129 +1,275 @@
-use crate::{credentials::GoogleCloudUserDirectory, Error};
-use serde::Deserialize;
-use std::io::BufRead;
+use crate::{
+    credentials::{process_env, Env, GoogleCloudUserDirectory},
+    Error,
+};
+use std::collections::HashMap;
 
-#[derive(Deserialize)]
+/// the properties of a gcloud configuration that credentials and defaults are read from
+#[derive(Debug, Clone)]
 pub struct ProfileSchema {
+    /// name of the configuration, e.g. `default`
+    pub name: String,
+    /// `[core] account`
     pub account: String,
-    pub project: String,
+    /// `[core] project`
+    pub project: Option<String>,
+    /// `[compute] region`
+    pub region: Option<String>,
+    /// `[billing] quota_project`
+    pub quota_project: Option<String>,
 }
 
 /// need to load config map into this struct
 pub struct GoogleCloudConfigurationContext {
     pub directory: GoogleCloudUserDirectory,
     pub profiles: Profiles,
+    /// name of the configuration the profiles were read from
+    pub name: String,
 }
 
 impl GoogleCloudConfigurationContext {
-    pub fn new() -> Result<Self, Error> {
-        let dir = GoogleCloudUserDirectory::new()?;
-        let profiles = Profiles::new(&dir)?;
+    /// reads the named configuration, or the active one the way gcloud resolves it
+    pub fn new(configuration: Option<&str>) -> Result<Self, Error> {
+        Self::with_directory(GoogleCloudUserDirectory::new()?, configuration)
+    }
+
+    pub fn with_directory(
+        directory: GoogleCloudUserDirectory,
+        configuration: Option<&str>,
+    ) -> Result<Self, Error> {
+        let name = configuration
+            .map(String::from)
+            .unwrap_or_else(|| directory.active_configuration());
+        let mut profiles = Profiles::new(&directory.get_configuration(&name))?;
+        profiles.env = directory.env;
         Ok(Self {
-            directory: dir,
+            directory,
             profiles,
+            name,
+        })
+    }
+
+    pub fn configuration(&self) -> Result<ProfileSchema, Error> {
+        let Some(account) = self.profiles.property("core", "account") else {
+            return Err(Error::InvalidProfile(format!(
+                "configuration `{}` has no `[core] account`",
+                self.name
+            )));
+        };
+
+        Ok(ProfileSchema {
+            name: self.name.clone(),
+            account,
+            project: self.profiles.property("core", "project"),
+            region: self.profiles.property("compute", "region"),
+            quota_project: self.profiles.property("billing", "quota_project"),
         })
     }
 }
 
+/// the sections of a gcloud configuration file
 pub struct Profiles {
-    inner: std::collections::HashMap<String, toml::Table>,
+    inner: Sections,
+    env: Env,
 }
 
 impl Profiles {
-    pub fn new(directory: &GoogleCloudUserDirectory) -> Result<Profiles, Error> {
-        let mut profiles = Profiles {
-            inner: std::collections::HashMap::<String, toml::Table>::new(),
-        };
-
-        let config = directory.get_config_default();
-        let contents = std::fs::read_to_string(config.as_path())
+    pub fn new(config: &std::path::Path) -> Result<Profiles, Error> {
+        let contents = std::fs::read_to_string(config)
             .map_err(|e| Error::FailedToLoad(format!("{} because {}", config.display(), e)))?;
 
-        parse(&mut profiles, contents.as_ref());
-
-        Ok(profiles)
+        Ok(Profiles {
+            inner: parse(&contents),
+            env: process_env,
+        })
     }
 
-    pub fn get(&mut self, name: &str) -> Result<ProfileSchema, Error> {
-        // read out the core profile
-        let Some(core) = self.inner.remove(name) else {
-            return Err(Error::ProfileNotFound(name.to_string()));
-        };
-
-        let profile: ProfileSchema = core
-            .try_into()
-            .map_err(|e| Error::InvalidProfile(e.to_string()))?;
+    /// `CLOUDSDK_<SECTION>_<PROPERTY>` overrides the file, like it does for gcloud
+    pub fn property(&self, section: &str, name: &str) -> Option<String> {
+        let variable = format!("CLOUDSDK_{}_{}", section, name).to_uppercase();
+        if let Some(value) = (self.env)(&variable) {
+            return Some(value);
+        }
 
-        Ok(profile)
+        self.inner
+            .get(section)?
+            .get(name)
+            .filter(|value| !value.is_empty())
+            .cloned()
     }
 }
 
-pub fn parse(profiles: &mut Profiles, contents: &[u8]) {
-    let mut lines = contents.lines();
-    while let Some(line) = lines.next() {
-        let Ok(line) = line else {
-            continue;
-        };
+/// properties by name, by section name
+pub type Sections = HashMap<String, HashMap<String, String>>;
 
-        let name = line.trim_matches(['[', ']']);
-        if name.is_empty() {
+/// reads an INI file the way python's configparser, which gcloud uses, does
+///
+/// - lines starting with `#` or `;` are comments
+/// - keys are separated from values by the first `=` or `:`, so values may contain either
+/// - an indented line continues the value before it, blank lines in between are kept in the value
+/// - keys are lowercase, section names are kept as written, so `[ core ]` is not `[core]`
+///
+/// where configparser raises, this is deliberately lenient as gcloud files are only read:
+/// a section repeated later adds to the first with later values winning, and lines that are
+/// not a section, property or continuation are skipped with a warning
+pub fn parse(contents: &str) -> Sections {
+    let mut sections = Sections::new();
+    let mut section: Option<String> = None;
+    // the key an indented line continues
+    let mut last: Option<String> = None;
+    // blank lines since the last line of the value, kept when the value continues after them
+    let mut blanks = 0;
+
+    for (number, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            blanks += 1;
+            continue;
+        }
+        if trimmed.starts_with(['#', ';']) {
             continue;
         }
 
-        // set name of profile and initialize table
-        let name = name.to_string();
-        let mut table = toml::Table::new();
-
-        // add properties to the profile
-        for property in lines.by_ref() {
-            // if there is an error, skip to the next property
-            let Ok(property) = property else {
+        if line.starts_with([' ', '\t']) {
+            let value = section
+                .as_ref()
+                .zip(last.as_ref())
+                .and_then(|(section, key)| sections.get_mut(section)?.get_mut(key));
+            if let Some(value) = value {
+                value.push_str(&"\n".repeat(blanks + 1));
+                value.push_str(trimmed);
+                blanks = 0;
                 continue;
-            };
-
-            // an empty line indicates the end of the profile
-            if property.is_empty() {
-                break;
             }
+        }
+        blanks = 0;
 
-            // read the property
-            let mut parts = property.split('=');
-            if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
-                table.insert(
-                    key.trim().to_string(),
-                    toml::Value::String(value.trim().to_string()),
-                );
-            }
+        if let Some(name) = trimmed.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let name = name.to_string();
+            sections.entry(name.clone()).or_default();
+            section = Some(name);
+            last = None;
+            continue;
         }
 
-        // add the profile to the profile config map
-        profiles.inner.insert(name, table);
+        let Some((key, value)) = trimmed.split_once(['=', ':']) else {
+            log::warn!("skipping line {}, expected `key = value`", number + 1);
+            continue;
+        };
+        let Some(section) = &section else {
+            log::warn!("skipping line {}, it is not in a section", number + 1);
+            continue;
+        };
+
+        let key = key.trim().to_lowercase();
+        sections
+            .entry(section.clone())
+            .or_default()
+            .insert(key.clone(), value.trim().to_string());
+        last = Some(key);
     }
-}
 
-// these tests will only work if gcloud is installed on the machine + a service account is loaded
+    sections
+}
 
-/* #[cfg(test)]
+#[cfg(test)]
 mod test {
-    use super::Profiles;
-    use crate::GoogleCloudUserDirectory;
+    use super::{parse, GoogleCloudConfigurationContext};
+    use crate::credentials::GoogleCloudUserDirectory;
+    use std::path::PathBuf;
+
+    fn fixtures() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/gcloud")
+    }
+
+    fn fixture(name: &str) -> String {
+        std::fs::read_to_string(fixtures().join("configurations").join(name)).unwrap()
+    }
+
+    #[test]
+    fn parses_gcloud_configuration() {
+        let sections = parse(&fixture("config_work"));
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections["core"]["account"], "me@example.com");
+        assert_eq!(sections["core"]["disable_usage_reporting"], "True");
+        assert_eq!(sections["compute"]["zone"], "europe-west1-b");
+        assert_eq!(sections["billing"]["quota_project"], "billing-project");
+    }
+
+    #[test]
+    fn parses_comments_continuations_and_duplicates() {
+        let sections = parse(&fixture("config_edge_cases"));
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[" core "]["project"], "spaced-section");
+
+        let core = &sections["core"];
+        assert_eq!(core["account"], "edge@example.com");
+        assert_eq!(core["project"], "edge-project-override");
+        assert_eq!(core["custom_ca_certs_file"], "/etc/ssl/certs/ca.pem");
+        assert!(core.keys().all(|key| !key.starts_with(['#', ';'])));
+
+        let auth = &sections["auth"];
+        assert_eq!(
+            auth["token_host"],
+            "https://oauth2.googleapis.com/token?a=b&c=d"
+        );
+        assert_eq!(
+            auth["scopes"],
+            "https://www.googleapis.com/auth/cloud-platform\nhttps://www.googleapis.com/auth/bigquery\n\nhttps://www.googleapis.com/auth/devstorage.read_only"
+        );
+        assert_eq!(auth.len(), 2);
+    }
 
     #[test]
-    pub fn deserializes_profiles() {
-        let directory = GoogleCloudUserDirectory::new().unwrap();
-        let profiles = Profiles::new(&directory);
-        assert!(profiles.is_ok());
+    fn skips_properties_outside_of_sections() {
+        let sections = parse(&fixture("config_no_section"));
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections["core"].get("account"), None);
+        assert_eq!(sections["core"]["project"], "orphan");
     }
 
     #[test]
-    pub fn finds_adc() {
-        let directory = GoogleCloudUserDirectory::new().unwrap();
-        let mut profiles = Profiles::new(&directory).unwrap();
+    fn reads_active_and_named_configurations() {
+        // the `CLOUDSDK_*` variables of whoever runs the tests are ignored
+        let directory = || GoogleCloudUserDirectory::with_root(fixtures()).with_env(|_| None);
 
-        let core_profile = profiles.get("core").unwrap();
+        let context = GoogleCloudConfigurationContext::with_directory(directory(), None).unwrap();
+        let configuration = context.configuration().unwrap();
+        assert_eq!(configuration.name, "work");
+        assert_eq!(configuration.account, "me@example.com");
+        assert_eq!(configuration.region.as_deref(), Some("europe-west1"));
+        assert_eq!(
+            configuration.quota_project.as_deref(),
+            Some("billing-project")
+        );
 
-        let credentials = directory.get_profile_adc(&core_profile);
-        let credentials = crate::credentials_from_file(credentials);
-        assert!(credentials.credentials().is_ok());
+        let context =
+            GoogleCloudConfigurationContext::with_directory(directory(), Some("default")).unwrap();
+        let configuration = context.configuration().unwrap();
+        assert_eq!(configuration.account, "other@example.com");
+        assert_eq!(configuration.region, None);
+        assert!(directory()
+            .get_profile_adc(&configuration)
+            .ends_with("legacy_credentials/other@example.com/adc.json"));
+
+        assert!(
+            GoogleCloudConfigurationContext::with_directory(directory(), Some("missing")).is_err()
+        );
+    }
+
+    #[test]
+    fn environment_overrides_the_configuration() {
+        let directory =
+            GoogleCloudUserDirectory::with_root(fixtures()).with_env(|name| match name {
+                "CLOUDSDK_ACTIVE_CONFIG_NAME" => Some("default".to_string()),
+                "CLOUDSDK_CORE_PROJECT" => Some("from-env".to_string()),
+                _ => None,
+            });
+
+        let context = GoogleCloudConfigurationContext::with_directory(directory, None).unwrap();
+        let configuration = context.configuration().unwrap();
+        assert_eq!(configuration.name, "default");
+        assert_eq!(configuration.account, "other@example.com");
+        assert_eq!(configuration.project.as_deref(), Some("from-env"));
     }
-} */
+}
@@ -0,0 +1,243 @@
+//! places credentials may be found, tried in order until one provides them
+
+use crate::{
+    credentials::GoogleCloudUserDirectory, from_environment_variable, from_file,
+    from_metadata_server, profile::GoogleCloudConfigurationContext, Authentication,
+    CredentialsSchema, Error,
+};
+use std::path::PathBuf;
+
+pub trait CredentialProvider {
+    /// credentials, or an `Authentication` whose error says why there were none
+    fn provide(&self) -> Authentication;
+}
+
+/// a json credentials file in an environment variable, e.g. `GOOGLE_APPLICATION_CREDENTIALS`
+pub struct EnvProvider(pub String);
+
+impl CredentialProvider for EnvProvider {
+    fn provide(&self) -> Authentication {
+        from_environment_variable(&self.0)
+    }
+}
+
+pub struct FileProvider(pub PathBuf);
+
+impl CredentialProvider for FileProvider {
+    fn provide(&self) -> Authentication {
+        from_file(&self.0)
+    }
+}
+
+/// the account of a gcloud configuration, the active one unless named
+#[derive(Default)]
+pub struct GcloudProvider {
+    pub configuration: Option<String>,
+}
+
+impl CredentialProvider for GcloudProvider {
+    fn provide(&self) -> Authentication {
+        let profile = GoogleCloudConfigurationContext::new(self.configuration.as_deref())
+            .and_then(|cxt| Ok((cxt.configuration()?, cxt.directory)));
+
+        match profile {
+            Ok((profile, directory)) => {
+                let mut auth = from_file(directory.get_profile_adc(&profile));
+                auth.core_profile = Some(profile);
+                auth
+            }
+            Err(e) => Authentication::new("gcloud", Err(e)),
+        }
+    }
+}
+
+/// created by `gcloud auth application-default login`
+pub struct ApplicationDefaultProvider;
+
+impl CredentialProvider for ApplicationDefaultProvider {
+    fn provide(&self) -> Authentication {
+        match GoogleCloudUserDirectory::new() {
+            Ok(directory) => from_file(directory.get_application_default_credentials()),
+            Err(e) => Authentication::new("application_default_credentials.json", Err(e)),
+        }
+    }
+}
+
+/// the service account of the GCE instance, Cloud Run service or GKE workload
+pub struct MetadataProvider;
+
+impl CredentialProvider for MetadataProvider {
+    fn provide(&self) -> Authentication {
+        from_metadata_server()
+    }
+}
+
+/// json credentials printed by a command, e.g. one that reads them from a secret manager
+pub struct ProcessProvider {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl CredentialProvider for ProcessProvider {
+    fn provide(&self) -> Authentication {
+        let command = std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let output = std::process::Command::new(&self.program)
+            .args(&self.args)
+            .output()
+            .map_err(|e| Error::FailedToLoad(format!("`{}` because {}", command, e)));
+        let credentials = output.and_then(|output| {
+            if !output.status.success() {
+                return Err(Error::FailedToLoad(format!(
+                    "`{}` because it exited with {}: {}",
+                    command,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )));
+            }
+            CredentialsSchema::deserialize(&String::from_utf8_lossy(&output.stdout))
+        });
+
+        Authentication::new(format!("process:{}", command), credentials)
+    }
+}
+
+/// providers in the order they are tried
+pub struct ProviderChain {
+    providers: Vec<Box<dyn CredentialProvider>>,
+}
+
+impl ProviderChain {
+    pub fn new() -> Self {
+        Self {
+            providers: Vec::new(),
+        }
+    }
+
+    /// `GOOGLE_APPLICATION_CREDENTIALS`, the gcloud configuration, application default credentials, then the metadata server
+    pub fn with_configuration(configuration: Option<&str>) -> Self {
+        Self::new()
+            .with(EnvProvider("GOOGLE_APPLICATION_CREDENTIALS".to_string()))
+            .with(GcloudProvider {
+                configuration: configuration.map(String::from),
+            })
+            .with(ApplicationDefaultProvider)
+            // only asked last, off google cloud it costs a connection timeout
+            .with(MetadataProvider)
+    }
+
+    /// tried after the providers already in the chain
+    pub fn with(mut self, provider: impl CredentialProvider + 'static) -> Self {
+        self.push(provider);
+        self
+    }
+
+    pub fn push(&mut self, provider: impl CredentialProvider + 'static) {
+        self.providers.push(Box::new(provider));
+    }
+
+    /// tried before the providers already in the chain
+    pub fn prepend(&mut self, provider: impl CredentialProvider + 'static) {
+        self.providers.insert(0, Box::new(provider));
+    }
+
+    /// the first credentials provided, later providers are not tried
+    pub fn authentication(&self) -> Option<Authentication> {
+        self.providers
+            .iter()
+            .map(|provider| provider.provide())
+            .find(|auth| auth.credentials.is_ok())
+    }
+
+    /// what every provider found, including the ones after the first that provides credentials
+    pub fn attempts(&self) -> Vec<Authentication> {
+        self.providers
+            .iter()
+            .map(|provider| provider.provide())
+            .collect()
+    }
+}
+
+impl Default for ProviderChain {
+    fn default() -> Self {
+        Self::with_configuration(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CredentialProvider, EnvProvider, FileProvider, ProcessProvider, ProviderChain};
+    use crate::{token::test::service_account_test, Authentication, CredentialsSchema};
+
+    struct Custom;
+
+    impl CredentialProvider for Custom {
+        fn provide(&self) -> Authentication {
+            Authentication::new(
+                "custom",
+                Ok(CredentialsSchema::ServiceAccount(service_account_test())),
+            )
+        }
+    }
+
+    #[test]
+    fn tries_providers_in_order() {
+        let chain = ProviderChain::new()
+            .with(EnvProvider(
+                "GAUTHENTICATOR_TRIES_PROVIDERS_IN_ORDER".to_string(),
+            ))
+            .with(FileProvider("/nonexistent/key.json".into()))
+            .with(Custom);
+
+        let authentication = chain.authentication().unwrap();
+        assert_eq!(authentication.loading_from(), "custom");
+
+        let attempts = chain.attempts();
+        assert_eq!(attempts.len(), 3);
+        assert!(attempts[0]
+            .message()
+            .contains("GAUTHENTICATOR_TRIES_PROVIDERS_IN_ORDER"));
+        assert!(attempts[1]
+            .message()
+            .contains("cannot load credentials from /nonexistent/key.json"));
+        assert_eq!(attempts[2].status(), "✅");
+
+        let mut chain = ProviderChain::new().with(FileProvider("/nonexistent/key.json".into()));
+        assert!(chain.authentication().is_none());
+        chain.prepend(Custom);
+        assert!(chain.authentication().is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_credentials_from_a_process() {
+        let path =
+            std::env::temp_dir().join("gauthenticator-reads-credentials-from-a-process.json");
+        std::fs::write(
+            &path,
+            serde_json::to_string(&service_account_test()).unwrap(),
+        )
+        .unwrap();
+
+        let provider = ProcessProvider {
+            program: "cat".to_string(),
+            args: vec![path.display().to_string()],
+        };
+        let authentication = provider.provide();
+        assert_eq!(authentication.project_id(), Some("test"));
+
+        let failing = ProcessProvider {
+            program: "false".to_string(),
+            args: Vec::new(),
+        };
+        assert!(failing
+            .provide()
+            .message()
+            .contains("`false` because it exited"));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
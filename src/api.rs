@@ -1,6 +1,13 @@
-use crate::query::{request::QueryRequest, response::QueryResponse};
+use crate::{
+    dataset::DatasetList,
+    job::{Job, JobList},
+    query::{request::QueryRequest, response::QueryResponse},
+    table::{Table, TableDataList, TableReference},
+};
 use ureq::Request;
 
+const BASE_URL: &str = "https://bigquery.googleapis.com/bigquery/v2";
+
 pub struct Client {
     host: String,
     token: String,
@@ -11,18 +18,33 @@ pub enum ContentType {
     None,
 }
 
+/// a request that failed, with the status line and the body of the response
+#[derive(Debug)]
+pub struct Error(String);
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
 impl Client {
     pub fn bq_client(token: String, project_id: &str) -> Self {
         Self {
             token,
-            host: format!(
-                "https://bigquery.googleapis.com/bigquery/v2/projects/{}",
-                project_id
-            ),
+            host: format!("{}/projects/{}", BASE_URL, project_id),
         }
     }
 
-    pub fn endpoint(&self, request: Request, body: ContentType) -> ureq::Response {
+    pub fn endpoint(&self, request: Request, body: ContentType) -> Result<ureq::Response, Error> {
         let request = request.set("AUTHORIZATION", &format!("Bearer {}", &self.token));
 
         let response = match body {
@@ -33,46 +55,160 @@ impl Client {
         Self::handle_error(response)
     }
 
+    fn json<T: serde::de::DeserializeOwned>(response: ureq::Response) -> Result<T, Error> {
+        response
+            .into_json()
+            .map_err(|e| Error(format!("unexpected response: {}", e)))
+    }
+
     /// <https://cloud.google.com/bigquery/docs/reference/rest/v2/jobs/getQueryResults>
-    pub fn jobs_query_results(&self, job_id: &str, location: &str) -> QueryResponse {
-        let response = self.endpoint(
-            ureq::get(&format!("{}/queries/{}", &self.host, job_id)).query("location", location),
-            ContentType::None,
-        );
+    pub fn jobs_query_results(&self, job_id: &str, location: &str) -> Result<QueryResponse, Error> {
+        self.jobs_query_results_page(job_id, location, None)
+    }
+
+    /// <https://cloud.google.com/bigquery/docs/reference/rest/v2/jobs/getQueryResults>
+    /// fetches the page of results identified by `page_token`
+    pub fn jobs_query_results_page(
+        &self,
+        job_id: &str,
+        location: &str,
+        page_token: Option<&str>,
+    ) -> Result<QueryResponse, Error> {
+        let mut request = ureq::get(&format!("{}/queries/{}", &self.host, job_id))
+            .query("location", location)
+            .query("formatOptions.useInt64Timestamp", "true");
+
+        if let Some(page_token) = page_token {
+            request = request.query("pageToken", page_token);
+        }
 
-        response.into_json().unwrap()
+        Self::json(self.endpoint(request, ContentType::None)?)
     }
 
     /// <https://cloud.google.com/bigquery/docs/reference/rest/v2/jobs/query>
-    /// the rows data is returned as a protobuf
-    pub fn jobs_query(&self, request: QueryRequest) -> QueryResponse {
-        let response = self.endpoint(
+    /// the rows data is returned as a protobuf, an incomplete query is polled until it finishes
+    pub fn jobs_query(&self, request: QueryRequest) -> Result<QueryResponse, Error> {
+        let body = serde_json::to_value(request).map_err(|e| Error(e.to_string()))?;
+        let response: QueryResponse = Self::json(self.endpoint(
             ureq::post(&format!("{}/queries", &self.host)),
-            ContentType::Json(serde_json::to_value(request).unwrap()),
-        );
-
-        let response: QueryResponse = response.into_json().unwrap();
+            ContentType::Json(body),
+        )?)?;
 
         response.retry(self)
     }
 
-    pub fn tables_list(&self, dataset_id: &str) -> ureq::Response {
+    /// <https://cloud.google.com/bigquery/docs/reference/rest/v2/jobs/insert>
+    /// starts the query without waiting for it to finish
+    pub fn jobs_insert(&self, request: &QueryRequest) -> Result<Job, Error> {
+        Self::json(self.endpoint(
+            ureq::post(&format!("{}/jobs", &self.host)),
+            ContentType::Json(request.to_job()),
+        )?)
+    }
+
+    /// <https://cloud.google.com/bigquery/docs/reference/rest/v2/jobs/list>
+    /// lists the child jobs of a script, most recent first
+    pub fn jobs_list(
+        &self,
+        parent_job_id: &str,
+        page_token: Option<&str>,
+    ) -> Result<JobList, Error> {
+        let mut request =
+            ureq::get(&format!("{}/jobs", &self.host)).query("parentJobId", parent_job_id);
+
+        if let Some(page_token) = page_token {
+            request = request.query("pageToken", page_token);
+        }
+
+        Self::json(self.endpoint(request, ContentType::None)?)
+    }
+
+    /// <https://cloud.google.com/bigquery/docs/reference/rest/v2/jobs/get>
+    /// `location` is only optional for jobs in the `US` and `EU` multi-regions
+    pub fn jobs_get(&self, job_id: &str, location: Option<&str>) -> Result<Job, Error> {
+        let mut request = ureq::get(&format!("{}/jobs/{}", &self.host, job_id));
+
+        if let Some(location) = location {
+            request = request.query("location", location);
+        }
+
+        Self::json(self.endpoint(request, ContentType::None)?)
+    }
+
+    /// <https://cloud.google.com/bigquery/docs/reference/rest/v2/datasets/list>
+    pub fn datasets_list(&self, page_token: Option<&str>) -> Result<DatasetList, Error> {
+        let mut request = ureq::get(&format!("{}/datasets", &self.host));
+
+        if let Some(page_token) = page_token {
+            request = request.query("pageToken", page_token);
+        }
+
+        Self::json(self.endpoint(request, ContentType::None)?)
+    }
+
+    pub fn tables_list(&self, dataset_id: &str) -> Result<ureq::Response, Error> {
         self.endpoint(
             ureq::get(&format!("{}/datasets/{}/tables", &self.host, dataset_id)),
             ContentType::None,
         )
     }
 
-    fn handle_error(result: Result<ureq::Response, ureq::Error>) -> ureq::Response {
+    /// <https://cloud.google.com/bigquery/docs/reference/rest/v2/tables/get>
+    pub fn tables_get(&self, dataset_id: &str, table_id: &str) -> Result<Table, Error> {
+        Self::json(self.endpoint(
+            ureq::get(&format!(
+                "{}/datasets/{}/tables/{}",
+                &self.host, dataset_id, table_id
+            )),
+            ContentType::None,
+        )?)
+    }
+
+    /// <https://cloud.google.com/bigquery/docs/reference/rest/v2/tables/get>
+    /// unlike `tables_get`, the table may belong to another project
+    pub fn tables_get_reference(&self, reference: &TableReference) -> Result<Table, Error> {
+        let response = self.endpoint(
+            ureq::get(&format!(
+                "{}/projects/{}/datasets/{}/tables/{}",
+                BASE_URL, reference.project_id, reference.dataset_id, reference.table_id
+            )),
+            ContentType::None,
+        )?;
+
+        Self::json(response)
+    }
+
+    /// <https://cloud.google.com/bigquery/docs/reference/rest/v2/tabledata/list>
+    /// reads rows straight from storage, which unlike a query is free of charge
+    pub fn tabledata_list(
+        &self,
+        reference: &TableReference,
+        max_results: u32,
+    ) -> Result<TableDataList, Error> {
+        let request = ureq::get(&format!(
+            "{}/projects/{}/datasets/{}/tables/{}/data",
+            BASE_URL, reference.project_id, reference.dataset_id, reference.table_id
+        ))
+        .query("maxResults", &max_results.to_string())
+        .query("formatOptions.useInt64Timestamp", "true");
+
+        Self::json(self.endpoint(request, ContentType::None)?)
+    }
+
+    fn handle_error(result: Result<ureq::Response, ureq::Error>) -> Result<ureq::Response, Error> {
         match result {
-            Ok(r) => r,
+            Ok(r) => Ok(r),
             Err(e) => {
                 let header = e.to_string();
                 let Some(response) = e.into_response() else {
-                    panic!("{:#?}", &header);
+                    return Err(Error(format!("{:#?}", &header)));
                 };
 
-                panic!("{}\n{}", header, response.into_string().unwrap());
+                Err(Error(format!(
+                    "{}\n{}",
+                    header,
+                    response.into_string().unwrap_or_default()
+                )))
             }
         }
     }
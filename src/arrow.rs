@@ -0,0 +1,511 @@
+//! conversion of query results into arrow [`RecordBatch`]es
+//!
+//! only available with the `arrow` feature enabled
+
+use crate::{
+    api::Client,
+    query::response::{JobReference, QueryResponse, TableFieldSchema, TableSchema},
+};
+use arrow::{
+    array::{
+        ArrayRef, BinaryArray, BooleanArray, Date32Array, Decimal128Array, Decimal256Array,
+        Float64Array, Int64Array, ListArray, RecordBatch, RecordBatchOptions, StringArray,
+        StructArray, Time64MicrosecondArray, TimestampMicrosecondArray,
+    },
+    buffer::{NullBuffer, OffsetBuffer},
+    compute::kernels::cast_utils::parse_decimal,
+    datatypes::{
+        DataType, Decimal128Type, Decimal256Type, Field, FieldRef, Fields, Schema, SchemaRef,
+        TimeUnit,
+    },
+    error::ArrowError,
+};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// default precision and scale of `NUMERIC`
+const NUMERIC: (u8, i8) = (38, 9);
+/// default precision and scale of `BIGNUMERIC`
+const BIGNUMERIC: (u8, i8) = (76, 38);
+
+impl TableSchema {
+    /// maps the bigquery schema onto an arrow schema
+    pub fn to_arrow(&self) -> Schema {
+        Schema::new(self.fields.iter().map(|f| f.to_arrow()).collect::<Vec<_>>())
+    }
+}
+
+impl TableFieldSchema {
+    /// maps the column onto an arrow field
+    ///
+    /// `REPEATED` columns become a non-nullable `List` of the underlying type,
+    /// the column description is kept under the `description` metadata key
+    pub fn to_arrow(&self) -> Field {
+        let field = if self.is_repeated() {
+            let item = Field::new("item", self.arrow_type(), false);
+            Field::new(&self.name, DataType::List(Arc::new(item)), false)
+        } else {
+            Field::new(&self.name, self.arrow_type(), self.mode != "REQUIRED")
+        };
+
+        match &self.description {
+            Some(description) => field.with_metadata(
+                [("description".to_string(), description.clone())]
+                    .into_iter()
+                    .collect(),
+            ),
+            None => field,
+        }
+    }
+
+    /// the arrow type of a single (non repeated) value of the column
+    fn arrow_type(&self) -> DataType {
+        match self.field_type.as_str() {
+            "INTEGER" | "INT64" => DataType::Int64,
+            "FLOAT" | "FLOAT64" => DataType::Float64,
+            "BOOLEAN" | "BOOL" => DataType::Boolean,
+            "BYTES" => DataType::Binary,
+            "NUMERIC" => {
+                let (precision, scale) = self.precision_and_scale(NUMERIC);
+                DataType::Decimal128(precision, scale)
+            }
+            "BIGNUMERIC" => {
+                let (precision, scale) = self.precision_and_scale(BIGNUMERIC);
+                DataType::Decimal256(precision, scale)
+            }
+            "TIMESTAMP" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            "DATETIME" => DataType::Timestamp(TimeUnit::Microsecond, None),
+            "DATE" => DataType::Date32,
+            "TIME" => DataType::Time64(TimeUnit::Microsecond),
+            "RECORD" | "STRUCT" => DataType::Struct(self.arrow_fields()),
+            // STRING, GEOGRAPHY, JSON, INTERVAL, RANGE
+            _ => DataType::Utf8,
+        }
+    }
+
+    fn arrow_fields(&self) -> Fields {
+        self.fields
+            .iter()
+            .flatten()
+            .map(|f| f.to_arrow())
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    /// parameterized decimals report their own precision and scale
+    fn precision_and_scale(&self, default: (u8, i8)) -> (u8, i8) {
+        let scale = self
+            .scale
+            .as_deref()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(default.1);
+        let precision = self
+            .precision
+            .as_deref()
+            .and_then(|p| p.parse::<u8>().ok())
+            // the integer part of the default type is always available
+            .map(|p| p.max(scale.max(0) as u8 + 1))
+            .unwrap_or(default.0)
+            .min(default.0);
+        (precision, scale)
+    }
+}
+
+impl QueryResponse {
+    /// converts the rows of this page into a single [`RecordBatch`]
+    pub fn to_record_batch(&self) -> Result<RecordBatch, ArrowError> {
+        let Some(schema) = &self.schema else {
+            return Err(ArrowError::SchemaError(
+                "query response does not have a schema".to_string(),
+            ));
+        };
+
+        record_batch(schema, &self.rows)
+    }
+
+    /// iterates over the results one page at a time, fetching subsequent pages as needed
+    pub fn into_record_batches(self, client: &Client) -> RecordBatches<'_> {
+        RecordBatches {
+            client,
+            schema: self.schema.clone(),
+            job_reference: self.job_reference.clone(),
+            page: Some(self),
+        }
+    }
+}
+
+/// converts raw `rows` (`{"f": [{"v": ...}]}`) into a [`RecordBatch`]
+pub fn record_batch(schema: &TableSchema, rows: &[Value]) -> Result<RecordBatch, ArrowError> {
+    let columns = schema
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let values: Vec<&Value> = rows.iter().map(|row| &row["f"][i]["v"]).collect();
+            column(field, &values)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let options = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+    RecordBatch::try_new_with_options(Arc::new(schema.to_arrow()), columns, &options)
+}
+
+/// an iterator of [`RecordBatch`]es, one per page of query results
+pub struct RecordBatches<'a> {
+    client: &'a Client,
+    /// pages after the first do not always repeat the schema
+    schema: Option<TableSchema>,
+    job_reference: JobReference,
+    page: Option<QueryResponse>,
+}
+
+impl RecordBatches<'_> {
+    /// the arrow schema shared by every batch
+    ///
+    /// only missing when the query did not produce a result set
+    pub fn schema(&self) -> Option<SchemaRef> {
+        self.schema.as_ref().map(|s| Arc::new(s.to_arrow()))
+    }
+
+    fn next_page(&self, page_token: &str) -> Result<QueryResponse, ArrowError> {
+        let Some(job_id) = &self.job_reference.job_id else {
+            return Err(ArrowError::InvalidArgumentError(
+                "cannot fetch the next page of a job without an id".to_string(),
+            ));
+        };
+
+        self.client
+            .jobs_query_results_page(job_id, &self.job_reference.location, Some(page_token))
+            .map_err(|e| ArrowError::ExternalError(Box::new(e)))
+    }
+}
+
+impl Iterator for RecordBatches<'_> {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let page = self.page.take()?;
+        let schema = self.schema.as_ref()?;
+        let batch = record_batch(schema, &page.rows);
+
+        if let Some(page_token) = &page.page_token {
+            match self.next_page(page_token) {
+                Ok(next) => self.page = Some(next),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        Some(batch)
+    }
+}
+
+/// builds the array for a single column from its cell values
+fn column(field: &TableFieldSchema, values: &[&Value]) -> Result<ArrayRef, ArrowError> {
+    if field.is_repeated() {
+        return list(field, values);
+    }
+
+    let nulls: Option<NullBuffer> = values.iter().any(|v| v.is_null()).then(|| {
+        values
+            .iter()
+            .map(|v| !v.is_null())
+            .collect::<Vec<_>>()
+            .into()
+    });
+
+    let array: ArrayRef = match field.arrow_type() {
+        DataType::Int64 => Arc::new(
+            values
+                .iter()
+                .map(|v| parse(v, field, |s| s.parse::<i64>().ok()))
+                .collect::<Result<Int64Array, _>>()?,
+        ),
+        DataType::Float64 => Arc::new(
+            values
+                .iter()
+                .map(|v| parse(v, field, parse_float))
+                .collect::<Result<Float64Array, _>>()?,
+        ),
+        DataType::Boolean => Arc::new(
+            values
+                .iter()
+                .map(|v| parse(v, field, parse_bool))
+                .collect::<Result<BooleanArray, _>>()?,
+        ),
+        DataType::Binary => {
+            let values = values
+                .iter()
+                .map(|v| parse(v, field, decode_base64))
+                .collect::<Result<Vec<_>, _>>()?;
+            Arc::new(BinaryArray::from_iter(values))
+        }
+        DataType::Decimal128(precision, scale) => Arc::new(
+            values
+                .iter()
+                .map(|v| {
+                    parse(v, field, |s| {
+                        parse_decimal::<Decimal128Type>(s, precision, scale).ok()
+                    })
+                })
+                .collect::<Result<Decimal128Array, _>>()?
+                .with_precision_and_scale(precision, scale)?,
+        ),
+        DataType::Decimal256(precision, scale) => Arc::new(
+            values
+                .iter()
+                .map(|v| {
+                    parse(v, field, |s| {
+                        parse_decimal::<Decimal256Type>(s, precision, scale).ok()
+                    })
+                })
+                .collect::<Result<Decimal256Array, _>>()?
+                .with_precision_and_scale(precision, scale)?,
+        ),
+        DataType::Timestamp(_, Some(_)) => Arc::new(
+            values
+                .iter()
+                .map(|v| parse(v, field, crate::value::timestamp_micros))
+                .collect::<Result<TimestampMicrosecondArray, _>>()?
+                .with_timezone("UTC"),
+        ),
+        DataType::Timestamp(_, None) => Arc::new(
+            values
+                .iter()
+                .map(|v| parse(v, field, parse_datetime))
+                .collect::<Result<TimestampMicrosecondArray, _>>()?,
+        ),
+        DataType::Date32 => Arc::new(
+            values
+                .iter()
+                .map(|v| parse(v, field, parse_date))
+                .collect::<Result<Date32Array, _>>()?,
+        ),
+        DataType::Time64(_) => Arc::new(
+            values
+                .iter()
+                .map(|v| parse(v, field, parse_time))
+                .collect::<Result<Time64MicrosecondArray, _>>()?,
+        ),
+        DataType::Struct(fields) => {
+            let children = field.fields.as_deref().unwrap_or_default();
+            let arrays = children
+                .iter()
+                .enumerate()
+                .map(|(i, child)| {
+                    let values: Vec<&Value> = values.iter().map(|v| &v["f"][i]["v"]).collect();
+                    column(child, &values)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            // a struct without fields has no child to take its length from
+            if arrays.is_empty() {
+                return Ok(Arc::new(StructArray::new_empty_fields(values.len(), nulls)));
+            }
+
+            Arc::new(StructArray::try_new(fields, arrays, nulls)?)
+        }
+        _ => Arc::new(
+            values
+                .iter()
+                .map(|v| parse(v, field, |s| Some(s.to_string())))
+                .collect::<Result<StringArray, _>>()?,
+        ),
+    };
+
+    Ok(array)
+}
+
+/// repeated values arrive as `[{"v": ...}, ...]`
+fn list(field: &TableFieldSchema, values: &[&Value]) -> Result<ArrayRef, ArrowError> {
+    let items: Vec<&[Value]> = values
+        .iter()
+        .map(|v| v.as_array().map(Vec::as_slice).unwrap_or_default())
+        .collect();
+
+    let flattened: Vec<&Value> = items
+        .iter()
+        .flat_map(|i| i.iter().map(|v| &v["v"]))
+        .collect();
+    let offsets = OffsetBuffer::from_lengths(items.iter().map(|i| i.len()));
+
+    let element = TableFieldSchema {
+        mode: "NULLABLE".to_string(),
+        ..field.clone()
+    };
+    let item: FieldRef = Arc::new(Field::new("item", element.arrow_type(), false));
+    let values = column(&element, &flattened)?;
+
+    Ok(Arc::new(ListArray::try_new(item, offsets, values, None)?))
+}
+
+/// extracts the string representation of a cell and parses it
+///
+/// `null` values stay `None`, anything that fails to parse is an error
+fn parse<T>(
+    value: &Value,
+    field: &TableFieldSchema,
+    parser: impl Fn(&str) -> Option<T>,
+) -> Result<Option<T>, ArrowError> {
+    let s = match value {
+        Value::Null => return Ok(None),
+        Value::String(s) => s.as_str(),
+        Value::Bool(true) => "true",
+        Value::Bool(false) => "false",
+        Value::Number(n) => {
+            return parser(&n.to_string())
+                .map(Some)
+                .ok_or_else(|| err(field, value))
+        }
+        _ => return Err(err(field, value)),
+    };
+
+    parser(s).map(Some).ok_or_else(|| err(field, value))
+}
+
+fn err(field: &TableFieldSchema, value: &Value) -> ArrowError {
+    ArrowError::ParseError(format!(
+        "cannot parse {} as {} for column `{}`",
+        value, field.field_type, field.name
+    ))
+}
+
+fn parse_float(s: &str) -> Option<f64> {
+    match s {
+        "NaN" => Some(f64::NAN),
+        "Infinity" => Some(f64::INFINITY),
+        "-Infinity" => Some(f64::NEG_INFINITY),
+        _ => s.parse().ok(),
+    }
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s {
+        "true" | "TRUE" => Some(true),
+        "false" | "FALSE" => Some(false),
+        _ => None,
+    }
+}
+
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    use base64::{engine::general_purpose, Engine as _};
+    general_purpose::STANDARD.decode(s).ok()
+}
+
+fn parse_datetime(s: &str) -> Option<i64> {
+    let datetime = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f"))
+        .ok()?;
+    Some(datetime.and_utc().timestamp_micros())
+}
+
+fn parse_date(s: &str) -> Option<i32> {
+    let date = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+    let epoch = chrono::NaiveDate::from_ymd_opt(1970, 1, 1)?;
+    i32::try_from((date - epoch).num_days()).ok()
+}
+
+fn parse_time(s: &str) -> Option<i64> {
+    use chrono::Timelike;
+    let time = chrono::NaiveTime::parse_from_str(s, "%H:%M:%S%.f").ok()?;
+    Some(time.num_seconds_from_midnight() as i64 * 1_000_000 + time.nanosecond() as i64 / 1_000)
+}
+
+#[cfg(test)]
+mod test {
+    use super::record_batch;
+    use crate::query::response::TableSchema;
+    use arrow::{
+        array::{Array, AsArray},
+        datatypes::{DataType, Decimal128Type, Int64Type, TimeUnit, TimestampMicrosecondType},
+    };
+
+    fn schema() -> TableSchema {
+        serde_json::from_value(serde_json::json!({
+            "fields": [
+                { "name": "id", "type": "INTEGER", "mode": "REQUIRED", "description": "primary key" },
+                { "name": "price", "type": "NUMERIC", "mode": "NULLABLE" },
+                { "name": "created", "type": "TIMESTAMP", "mode": "NULLABLE" },
+                { "name": "tags", "type": "STRING", "mode": "REPEATED" },
+                { "name": "owner", "type": "RECORD", "mode": "NULLABLE", "fields": [
+                    { "name": "name", "type": "STRING", "mode": "NULLABLE" },
+                    { "name": "age", "type": "INT64", "mode": "NULLABLE" }
+                ]}
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn maps_schema() {
+        let schema = schema().to_arrow();
+        assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+        assert!(!schema.field(0).is_nullable());
+        assert_eq!(schema.field(0).metadata()["description"], "primary key");
+        assert_eq!(schema.field(1).data_type(), &DataType::Decimal128(38, 9));
+        assert_eq!(
+            schema.field(2).data_type(),
+            &DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+        );
+        assert!(matches!(schema.field(3).data_type(), DataType::List(_)));
+        assert!(matches!(schema.field(4).data_type(), DataType::Struct(f) if f.len() == 2));
+    }
+
+    #[test]
+    fn converts_rows() {
+        let rows = vec![
+            serde_json::json!({ "f": [
+                { "v": "1" },
+                { "v": "12.5" },
+                { "v": "1.7E9" },
+                { "v": [{ "v": "a" }, { "v": "b" }] },
+                { "v": { "f": [{ "v": "isaac" }, { "v": "30" }] } }
+            ]}),
+            serde_json::json!({ "f": [
+                { "v": "2" },
+                { "v": null },
+                { "v": null },
+                { "v": [] },
+                { "v": null }
+            ]}),
+        ];
+
+        let batch = record_batch(&schema(), &rows).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+
+        let ids = batch.column(0).as_primitive::<Int64Type>();
+        assert_eq!(ids.values(), &[1, 2]);
+
+        let price = batch.column(1).as_primitive::<Decimal128Type>();
+        assert_eq!(price.value(0), 12_500_000_000);
+        assert!(price.is_null(1));
+
+        let created = batch.column(2).as_primitive::<TimestampMicrosecondType>();
+        assert_eq!(created.value(0), 1_700_000_000_000_000);
+
+        let tags = batch.column(3).as_list::<i32>();
+        assert_eq!(tags.value_length(0), 2);
+        assert_eq!(tags.value_length(1), 0);
+
+        let owner = batch.column(4).as_struct();
+        assert!(owner.is_null(1));
+        assert_eq!(owner.column(0).as_string::<i32>().value(0), "isaac");
+    }
+
+    #[test]
+    fn converts_records_without_fields() {
+        let schema: TableSchema = serde_json::from_value(serde_json::json!({
+            "fields": [{ "name": "empty", "type": "RECORD", "mode": "NULLABLE", "fields": [] }]
+        }))
+        .unwrap();
+        let rows = vec![
+            serde_json::json!({ "f": [{ "v": { "f": [] } }] }),
+            serde_json::json!({ "f": [{ "v": null }] }),
+        ];
+
+        let batch = record_batch(&schema, &rows).unwrap();
+        let empty = batch.column(0).as_struct();
+        assert_eq!(empty.len(), 2);
+        assert_eq!(empty.num_columns(), 0);
+        assert!(empty.is_null(1));
+    }
+}
@@ -0,0 +1,390 @@
+//! results of `SELECT` queries kept on disk, so repeating a query does not run a job
+//!
+//! an entry is only served until it expires or one of the tables it read is modified
+
+use crate::{
+    api::{Client, Error},
+    job::Job,
+    query::{request::QueryRequest, response::QueryResponse},
+    table::TableReference,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Cached {
+    pub key: String,
+    pub project_id: String,
+    pub query: String,
+    /// seconds since the epoch
+    pub cached_at: i64,
+    pub expires_at: i64,
+    pub sources: Vec<Source>,
+    /// billed by the job that produced the results, which each hit saves
+    pub bytes_billed: u64,
+    pub hits: u64,
+    pub response: QueryResponse,
+}
+
+/// a table the query read and when it was last modified, at the time the query ran
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Source {
+    #[serde(flatten)]
+    pub table: TableReference,
+    pub last_modified_time: Option<String>,
+}
+
+/// every hit since the cache was first used, entries that are cleared keep counting
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct Totals {
+    pub hits: u64,
+    pub bytes_saved: u64,
+}
+
+impl Cached {
+    pub fn new(
+        key: String,
+        query: String,
+        ttl: Duration,
+        job: &Job,
+        sources: Vec<Source>,
+        response: QueryResponse,
+    ) -> Self {
+        let statistics = job.statistics.as_ref().and_then(|s| s.query.as_ref());
+        let now = chrono::Utc::now().timestamp();
+        Self {
+            key,
+            project_id: response.job_reference.project_id.clone(),
+            query,
+            cached_at: now,
+            expires_at: now.saturating_add(ttl.as_secs().try_into().unwrap_or(i64::MAX)),
+            sources,
+            bytes_billed: statistics
+                .and_then(|s| s.total_bytes_billed.as_deref())
+                .and_then(|b| b.parse().ok())
+                .unwrap_or_default(),
+            hits: 0,
+            response,
+        }
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        now >= self.expires_at
+    }
+
+    /// whether none of the tables were modified since the results were cached
+    ///
+    /// runs no job, but reads the metadata of each table with a free `tables.get` request until one was modified
+    pub fn is_current(&self, client: &Client) -> Result<bool, Error> {
+        for source in &self.sources {
+            let table = client.tables_get_reference(&source.table)?;
+            if table.last_modified_time != source.last_modified_time {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// reads when each table the job referenced was last modified, to check cached results against later
+pub fn sources(client: &Client, job: &Job) -> Result<Vec<Source>, Error> {
+    let statistics = job.statistics.as_ref().and_then(|s| s.query.as_ref());
+    statistics
+        .map(|s| s.referenced_tables.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|table| {
+            Ok(Source {
+                last_modified_time: client.tables_get_reference(&table)?.last_modified_time,
+                table,
+            })
+        })
+        .collect()
+}
+
+/// hex encoded sha256 of the normalized query and the settings that change its results
+pub fn key(request: &QueryRequest, project_id: &str) -> String {
+    let request = serde_json::to_value(request).unwrap_or_default();
+    let query = normalize(request["query"].as_str().unwrap_or_default());
+
+    let mut hasher = Sha256::new();
+    for part in [
+        query.as_str(),
+        project_id,
+        &request["default_dataset"].to_string(),
+        &request["location"].to_string(),
+        &request["query_parameters"].to_string(),
+        &request["use_legacy_sql"].to_string(),
+    ] {
+        hasher.update(part.as_bytes());
+        // keeps `ab` + `c` apart from `a` + `bc`
+        hasher.update([0]);
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// functions whose results change from one run to the next, which BigQuery does not cache either
+const NON_DETERMINISTIC: [&str; 9] = [
+    "CURRENT_TIMESTAMP",
+    "CURRENT_DATE",
+    "CURRENT_DATETIME",
+    "CURRENT_TIME",
+    "RAND",
+    "GENERATE_UUID",
+    "SESSION_USER",
+    "NOW",
+    "UNIX_MILLIS_NOW",
+];
+
+/// whether the query uses none of the non-deterministic functions, outside of strings and quoted names
+///
+/// a column named like one of them also counts, which only costs a cache entry
+pub fn is_deterministic(query: &str) -> bool {
+    let query = normalize(query);
+    let mut chars = query.chars().peekable();
+    let mut word = String::new();
+
+    while let Some(c) = chars.next() {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c.to_ascii_uppercase());
+            continue;
+        }
+        if NON_DETERMINISTIC.contains(&word.as_str()) {
+            return false;
+        }
+        word.clear();
+
+        if matches!(c, '\'' | '"' | '`') {
+            while let Some(next) = chars.next() {
+                match next {
+                    '\\' => {
+                        chars.next();
+                    }
+                    next if next == c => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    !NON_DETERMINISTIC.contains(&word.as_str())
+}
+
+/// drops comments and a trailing `;` and collapses whitespace, strings and quoted names are left as they are
+pub fn normalize(query: &str) -> String {
+    let mut normalized = String::with_capacity(query.len());
+    let mut chars = query.chars().peekable();
+    let mut space = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '-' if chars.peek() == Some(&'-') => {
+                chars.by_ref().take_while(|c| *c != '\n').for_each(drop);
+                space = true;
+            }
+            '#' => {
+                chars.by_ref().take_while(|c| *c != '\n').for_each(drop);
+                space = true;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                space = true;
+            }
+            c if c.is_whitespace() => space = true,
+            _ => {
+                if space && !normalized.is_empty() {
+                    normalized.push(' ');
+                }
+                space = false;
+                normalized.push(c);
+
+                if matches!(c, '\'' | '"' | '`') {
+                    while let Some(next) = chars.next() {
+                        normalized.push(next);
+                        match next {
+                            '\\' => normalized.extend(chars.next()),
+                            next if next == c => break,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    normalized.trim_end_matches([';', ' ']).to_string()
+}
+
+/// one json file per entry, named by its key
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    fn totals_path(&self) -> PathBuf {
+        self.dir.join("totals.json")
+    }
+
+    /// an entry that has not expired and whose tables are unchanged, stale entries are removed
+    ///
+    /// an entry is stale too when its tables cannot be read, e.g. because one was dropped
+    pub fn get(&self, client: &Client, key: &str) -> Option<Cached> {
+        let path = self.path(key);
+        let cached: Cached = read(&path)?;
+
+        let expired = cached.is_expired(chrono::Utc::now().timestamp());
+        if expired || !cached.is_current(client).unwrap_or(false) {
+            let _ = std::fs::remove_file(path);
+            return None;
+        }
+
+        Some(cached)
+    }
+
+    pub fn put(&self, cached: &Cached) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.path(&cached.key), serde_json::to_string(cached)?)
+    }
+
+    /// counts a hit on the entry and the bytes it saved
+    pub fn hit(&self, cached: &mut Cached) -> std::io::Result<()> {
+        cached.hits += 1;
+        self.put(cached)?;
+
+        let mut totals = self.totals();
+        totals.hits += 1;
+        totals.bytes_saved += cached.bytes_billed;
+        std::fs::write(self.totals_path(), serde_json::to_string(&totals)?)
+    }
+
+    pub fn totals(&self) -> Totals {
+        read(&self.totals_path()).unwrap_or_default()
+    }
+
+    /// entries that have expired are left out, most recently cached last
+    pub fn entries(&self) -> std::io::Result<Vec<Cached>> {
+        let now = chrono::Utc::now().timestamp();
+        let mut entries: Vec<Cached> = self
+            .files()?
+            .iter()
+            .filter_map(|path| read(path))
+            .filter(|cached: &Cached| !cached.is_expired(now))
+            .collect();
+
+        entries.sort_by_key(|cached| cached.cached_at);
+        Ok(entries)
+    }
+
+    /// removes every entry and returns how many there were, the totals are kept
+    pub fn clear(&self) -> std::io::Result<usize> {
+        let files = self.files()?;
+        for path in &files {
+            std::fs::remove_file(path)?;
+        }
+        Ok(files.len())
+    }
+
+    fn files(&self) -> std::io::Result<Vec<PathBuf>> {
+        let totals = self.totals_path();
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut files = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") && path != totals {
+                files.push(path);
+            }
+        }
+        Ok(files)
+    }
+}
+
+fn read<T: serde::de::DeserializeOwned>(path: &Path) -> Option<T> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_deterministic, key, normalize};
+    use crate::query::request::QueryRequestBuilder;
+
+    #[test]
+    fn finds_non_deterministic_functions() {
+        assert!(is_deterministic("SELECT a FROM t WHERE d > '2024-01-01'"));
+        assert!(is_deterministic(
+            "SELECT 'CURRENT_TIMESTAMP()' AS s, `rand` FROM t -- RAND()"
+        ));
+        assert!(is_deterministic("SELECT current_timestamp_ms FROM t"));
+        assert!(!is_deterministic("SELECT current_timestamp() AS now"));
+        assert!(!is_deterministic("SELECT * FROM t WHERE d = CURRENT_DATE"));
+        assert!(!is_deterministic("SELECT RAND () FROM t"));
+        assert!(!is_deterministic("SELECT SESSION_USER()"));
+    }
+
+    #[test]
+    fn normalizes_queries() {
+        assert_eq!(
+            normalize("SELECT  a,\n\tb -- the columns\nFROM t /* all */ ;\n"),
+            "SELECT a, b FROM t"
+        );
+        assert_eq!(
+            normalize("SELECT 'a  -- b' FROM `my  table`"),
+            "SELECT 'a  -- b' FROM `my  table`"
+        );
+    }
+
+    #[test]
+    fn keys_ignore_formatting() {
+        let request = |query: &str| QueryRequestBuilder::new(query.to_string()).build();
+
+        assert_eq!(
+            key(&request("SELECT 1"), "test"),
+            key(&request("SELECT\n  1;"), "test")
+        );
+        assert_ne!(
+            key(&request("SELECT 1"), "test"),
+            key(&request("SELECT 1"), "other")
+        );
+        assert_ne!(
+            key(&request("SELECT 'a'"), "test"),
+            key(&request("SELECT 'A'"), "test")
+        );
+        assert_ne!(
+            key(&request("SELECT 1"), "test"),
+            key(
+                &QueryRequestBuilder::new("SELECT 1".to_string())
+                    .location("EU".to_string())
+                    .build(),
+                "test"
+            )
+        );
+    }
+}
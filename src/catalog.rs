@@ -0,0 +1,298 @@
+//! a local cache of the datasets, tables and columns of a project, used for completions
+
+use crate::{
+    api::{Client, Error},
+    query::request::{QueryRequest, QueryRequestBuilder},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+    time::Duration,
+};
+
+/// keywords that are followed by a table name
+const TABLE_KEYWORDS: [&str; 6] = ["FROM", "JOIN", "INTO", "UPDATE", "TABLE", "\\D"];
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct Catalog {
+    pub project_id: String,
+    /// seconds since the epoch
+    pub fetched_at: i64,
+    /// dataset → table → columns
+    pub datasets: BTreeMap<String, BTreeMap<String, Vec<Column>>>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Column {
+    pub name: String,
+    pub data_type: String,
+}
+
+impl Catalog {
+    /// lists the datasets of the project and reads their columns with one `INFORMATION_SCHEMA.COLUMNS`
+    /// query per region, labelled and capped like any other query
+    ///
+    /// a failed request leaves out the tables it would have read instead of failing the fetch, the
+    /// failures are returned with the partial catalog
+    pub fn fetch(
+        client: &Client,
+        project_id: &str,
+        labels: &HashMap<String, String>,
+        maximum_bytes_billed: Option<u64>,
+    ) -> (Self, Vec<Error>) {
+        let mut datasets = BTreeMap::new();
+        let mut regions: BTreeSet<String> = BTreeSet::new();
+        let mut failures = Vec::new();
+        let mut page_token = None;
+
+        loop {
+            let page = match client.datasets_list(page_token.as_deref()) {
+                Ok(page) => page,
+                Err(e) => {
+                    failures.push(e);
+                    break;
+                }
+            };
+            for dataset in page.datasets {
+                datasets.insert(dataset.dataset_reference.dataset_id, BTreeMap::new());
+                regions.insert(dataset.location.to_lowercase());
+            }
+
+            page_token = page.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+
+        for region in regions {
+            let query = format!(
+                "SELECT table_schema, table_name, column_name, data_type FROM `{}`.`region-{}`.INFORMATION_SCHEMA.COLUMNS ORDER BY table_schema, table_name, ordinal_position",
+                project_id, region
+            );
+            let mut request = QueryRequestBuilder::new(query).location(region);
+            if let Some(bytes) = maximum_bytes_billed {
+                request = request.maximum_bytes_billed(bytes);
+            }
+            if !labels.is_empty() {
+                request = request.labels(labels.clone());
+            }
+
+            if let Err(e) = Self::columns(client, request.build(), &mut datasets) {
+                failures.push(e);
+            }
+        }
+
+        let catalog = Self {
+            project_id: project_id.to_string(),
+            fetched_at: chrono::Utc::now().timestamp(),
+            datasets,
+        };
+        (catalog, failures)
+    }
+
+    /// adds the `table_schema, table_name, column_name, data_type` rows of every page to the listed datasets
+    fn columns(
+        client: &Client,
+        request: QueryRequest,
+        datasets: &mut BTreeMap<String, BTreeMap<String, Vec<Column>>>,
+    ) -> Result<(), Error> {
+        let mut response = client.jobs_query(request)?;
+
+        loop {
+            for row in &response.rows {
+                let text = |i: usize| row["f"][i]["v"].as_str().unwrap_or_default().to_string();
+                // the region also has the hidden datasets the list leaves out
+                if let Some(tables) = datasets.get_mut(&text(0)) {
+                    tables.entry(text(1)).or_default().push(Column {
+                        name: text(2),
+                        data_type: text(3),
+                    });
+                }
+            }
+
+            let (Some(page_token), Some(job_id)) = (
+                response.page_token.clone(),
+                response.job_reference.job_id.clone(),
+            ) else {
+                return Ok(());
+            };
+            response = client.jobs_query_results_page(
+                &job_id,
+                &response.job_reference.location,
+                Some(&page_token),
+            )?;
+        }
+    }
+
+    /// `None` when the file does not exist or is not a catalog
+    pub fn load(path: &Path) -> Option<Self> {
+        let contents = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string(self)?)
+    }
+
+    pub fn is_stale(&self, ttl: Duration) -> bool {
+        chrono::Utc::now().timestamp() - self.fetched_at >= ttl.as_secs() as i64
+    }
+
+    /// completes `dataset.` and `dataset.table`, optionally qualified by the project and quoted with backticks
+    pub fn complete_table(&self, word: &str) -> Vec<String> {
+        let quote = if word.starts_with('`') { "`" } else { "" };
+        let word = word.trim_start_matches('`');
+
+        let project = format!("{}.", self.project_id);
+        let (project, word) = match word.strip_prefix(&project) {
+            Some(rest) => (project.as_str(), rest),
+            None => ("", word),
+        };
+
+        let names: Vec<String> = match word.split_once('.') {
+            None => self
+                .datasets
+                .keys()
+                .filter(|d| d.starts_with(word))
+                .map(|d| format!("{}.", d))
+                .collect(),
+            Some((dataset, prefix)) => self
+                .datasets
+                .get(dataset)
+                .into_iter()
+                .flat_map(|tables| tables.keys())
+                .filter(|t| t.starts_with(prefix))
+                .map(|t| format!("{}.{}", dataset, t))
+                .collect(),
+        };
+
+        names
+            .into_iter()
+            .map(|name| format!("{}{}{}", quote, project, name))
+            .collect()
+    }
+
+    /// completes the columns of the tables that `statement` reads from, `alias.column` keeps its qualifier
+    pub fn complete_column(&self, statement: &str, word: &str) -> Vec<String> {
+        let (qualifier, prefix) = match word.rsplit_once('.') {
+            Some((qualifier, prefix)) => (format!("{}.", qualifier), prefix),
+            None => (String::new(), word),
+        };
+        let prefix = prefix.to_lowercase();
+
+        let tokens: Vec<&str> = statement
+            .split(|c: char| c.is_whitespace() || matches!(c, ',' | '(' | ')' | ';'))
+            .filter(|t| !t.is_empty())
+            .collect();
+
+        let columns: BTreeSet<&str> = tokens
+            .windows(2)
+            .filter(|pair| TABLE_KEYWORDS.contains(&pair[0].to_uppercase().as_str()))
+            .filter_map(|pair| self.table(pair[1]))
+            .flatten()
+            .map(|c| c.name.as_str())
+            .filter(|name| name.to_lowercase().starts_with(&prefix))
+            .collect();
+
+        columns
+            .into_iter()
+            .map(|name| format!("{}{}", qualifier, name))
+            .collect()
+    }
+
+    /// columns of a `dataset.table` or `project.dataset.table` reference
+    fn table(&self, reference: &str) -> Option<&Vec<Column>> {
+        let reference = reference.trim_matches('`');
+        let project = format!("{}.", self.project_id);
+        let reference = reference.strip_prefix(&project).unwrap_or(reference);
+        let (dataset, table) = reference.split_once('.')?;
+        self.datasets.get(dataset)?.get(table)
+    }
+
+    /// completes the word at the end of `text`, tables after `FROM` or `JOIN` and columns elsewhere
+    ///
+    /// returns the byte offset where the completed word starts
+    pub fn complete(&self, text: &str) -> (usize, Vec<String>) {
+        let start = text
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '`')))
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        let word = &text[start..];
+
+        let previous = text[..start]
+            .split_whitespace()
+            .last()
+            .unwrap_or_default()
+            .to_uppercase();
+
+        if TABLE_KEYWORDS.contains(&previous.as_str()) {
+            return (start, self.complete_table(word));
+        }
+
+        let mut candidates = self.complete_column(text, word);
+        if word.contains('.') {
+            candidates.extend(self.complete_table(word));
+        }
+
+        (start, candidates)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Catalog, Column};
+
+    fn catalog() -> Catalog {
+        let column = |name: &str| Column {
+            name: name.to_string(),
+            data_type: "STRING".to_string(),
+        };
+
+        let mut catalog = Catalog {
+            project_id: "test".to_string(),
+            ..Default::default()
+        };
+        let sales = catalog.datasets.entry("sales".to_string()).or_default();
+        sales.insert(
+            "orders".to_string(),
+            vec![column("order_id"), column("owner")],
+        );
+        sales.insert("owners".to_string(), vec![column("name")]);
+        catalog.datasets.entry("staging".to_string()).or_default();
+        catalog
+    }
+
+    #[test]
+    fn completes_tables() {
+        let catalog = catalog();
+
+        assert_eq!(catalog.complete_table("s"), vec!["sales.", "staging."]);
+        assert_eq!(catalog.complete_table("sales.ord"), vec!["sales.orders"]);
+        assert_eq!(
+            catalog.complete_table("`test.sales.ow"),
+            vec!["`test.sales.owners"]
+        );
+        assert!(catalog.complete_table("missing.").is_empty());
+    }
+
+    #[test]
+    fn completes_in_context() {
+        let catalog = catalog();
+
+        let (start, candidates) = catalog.complete("SELECT * FROM sales.o");
+        assert_eq!(start, 14);
+        assert_eq!(candidates, vec!["sales.orders", "sales.owners"]);
+
+        let (start, candidates) = catalog.complete("SELECT o.ow");
+        assert_eq!(start, 7);
+        assert!(candidates.is_empty());
+
+        let (_, candidates) = catalog.complete("SELECT o.ow FROM sales.orders o WHERE o.ow");
+        assert_eq!(candidates, vec!["o.owner"]);
+    }
+}
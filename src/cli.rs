@@ -1,18 +1,98 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use crate::{
+    config::{Config, CredentialSource, Editor},
+    history::{Entry, Filter, History, Status},
+    sessions::{Saved, Sessions},
+    shell::Shell,
+};
+use bq_rs::{
+    cache::{Cache, Cached},
+    catalog::Catalog,
+    job::{Job, Summary},
+    policy::Access,
+    query::response::QueryResponse,
+    script::Failure,
+    session::Session,
+    table::TableReference,
+};
+use bq_rs::{
+    query::request::{DatasetReference, QueryRequestBuilder},
+    value::{FormatOptions, GeographyFormat},
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap_complete::engine::{ArgValueCompleter, CompletionCandidate};
+use gauthenticator::TokenRequest;
+use std::{collections::HashMap, ffi::OsStr, io::IsTerminal, path::PathBuf, time::Duration};
+
+/// the scope of exchanged tokens when only a subject is given
+const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+/// how long the catalog used for completions is kept before it is fetched again
+const CATALOG_TTL: Duration = Duration::from_secs(24 * 60 * 60);
 
 #[derive(Debug, Parser)]
 #[command(name = "bq-rs")]
 #[command(about = "bigquery CLI client written in rust", long_about = None)]
 pub struct Cli {
     /// Path to service account key
-    #[arg(short, long)]
+    #[arg(short, long, env = "BQRS_KEY")]
     key: Option<PathBuf>,
 
+    /// Service account a `.p12` key belongs to, which unlike json keys it does not name
+    #[arg(long, value_name = "EMAIL", env = "BQRS_SERVICE_ACCOUNT_EMAIL")]
+    service_account_email: Option<String>,
+
     /// Project id
-    #[arg(short, long)]
+    #[arg(short, long, env = "BQRS_PROJECT")]
     project_id: Option<String>,
 
+    /// Profile of the config file to use for defaults
+    #[arg(long, env = "BQRS_PROFILE")]
+    profile: Option<String>,
+
+    /// gcloud configuration to read credentials and the project from instead of the active one
+    #[arg(long, value_name = "NAME")]
+    configuration: Option<String>,
+
+    /// Location to run queries in, e.g. `US` or `europe-west2`
+    #[arg(long, env = "BQRS_LOCATION")]
+    location: Option<String>,
+
+    /// Default dataset for unqualified table names, `dataset` or `project.dataset`
+    #[arg(long, env = "BQRS_DATASET", add = ArgValueCompleter::new(complete_dataset))]
+    dataset: Option<String>,
+
+    /// Queries that would bill more bytes than this fail without incurring a charge
+    #[arg(long, env = "BQRS_MAXIMUM_BYTES_BILLED")]
+    maximum_bytes_billed: Option<u64>,
+
+    /// Label attached to query jobs, e.g. `--label team=analytics`
+    #[arg(long = "label", value_parser = label)]
+    labels: Vec<(String, String)>,
+
+    /// OAuth2 scope to exchange a service account key for, e.g. to query Drive-backed tables
+    #[arg(long = "scope")]
+    scopes: Vec<String>,
+
+    /// User to act as with domain-wide delegation, requires an exchanged token
+    #[arg(long)]
+    subject: Option<String>,
+
+    /// Service account to act as, with a comma separated chain of delegates before it, e.g. `hop@p.iam.gserviceaccount.com,prod@p.iam.gserviceaccount.com`
+    #[arg(long, value_name = "EMAILS")]
+    impersonate_service_account: Option<String>,
+
+    /// Seconds impersonated tokens are valid for, more than 3600 needs an organization policy
+    #[arg(long, value_name = "SECONDS")]
+    impersonation_lifetime: Option<u64>,
+
+    /// Allow statements that write, e.g. INSERT, CREATE or DROP, which safe mode refuses
+    #[arg(long)]
+    allow_write: bool,
+
+    /// Run DROP and TRUNCATE statements without asking for confirmation
+    #[arg(short, long)]
+    yes: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -21,18 +101,436 @@ pub struct Cli {
 enum Commands {
     Query {
         query: String,
-        #[arg(short, long)]
-        format: Option<String>,
+        #[command(flatten)]
+        args: QueryArgs,
+    },
+    /// queries run before, recorded with their job, cost and outcome
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+    Jobs {
+        #[command(subcommand)]
+        command: JobsCommand,
     },
     DatasetList {
+        #[arg(add = ArgValueCompleter::new(complete_dataset))]
         id: String,
     },
+    /// print the first rows of a table, reading them is free unlike a query
+    Head {
+        /// `dataset.table` or `project.dataset.table`
+        #[arg(add = ArgValueCompleter::new(complete_table))]
+        table: String,
+        #[arg(short = 'n', long, default_value_t = 10)]
+        rows: u32,
+        /// Defaults to `table` when stdout is a terminal, otherwise `csv`
+        #[arg(short, long, value_enum)]
+        format: Option<Format>,
+        /// Text of NULL values, defaults to `NULL` and to nothing in `csv` and `tsv`
+        #[arg(long, value_name = "TEXT")]
+        null: Option<String>,
+    },
+    /// sessions keep temporary tables, variables and transactions between queries
+    Session {
+        #[command(subcommand)]
+        command: SessionCommand,
+    },
+    /// results kept by `query --cache`
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// manage the cached datasets, tables and columns used for completions
+    Catalog {
+        #[command(subcommand)]
+        command: CatalogCommand,
+    },
+    /// print an access token, or a self-signed JWT for `--audience`
     Token {
         #[arg(short, long)]
         audience: Option<String>,
+        /// Print a google-signed ID token for the audience, which Cloud Run and IAP accept
+        #[arg(long, requires = "audience")]
+        id_token: bool,
     },
     /// get information on the current environment
     Info,
+    /// sign in without the gcloud sdk
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommand,
+    },
+    /// interactive sql shell, statements end with `;` and share a session
+    Shell {
+        /// Defaults to `table`
+        #[arg(short, long, value_enum)]
+        format: Option<Format>,
+        /// Text of NULL values, defaults to `NULL` and to nothing in `csv` and `tsv`
+        #[arg(long, value_name = "TEXT")]
+        null: Option<String>,
+    },
+    /// inspect and edit the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+enum SessionCommand {
+    /// start a session and print its id
+    Start {
+        /// Name to refer to the session by in `--session`
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// terminate a session, its temporary tables are dropped
+    End { session: String },
+    /// list the sessions started here that are still active
+    List,
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+enum AuthCommand {
+    /// sign in with a browser and save the credentials as application default credentials
+    Login {
+        /// Print the url to sign in on instead of opening a browser, then paste the url it redirects to, e.g. over ssh
+        #[arg(long)]
+        no_browser: bool,
+        /// Where to save the credentials, defaults to gcloud's `application_default_credentials.json`
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// `client_secret.json` of the desktop OAuth client to sign in with
+        #[arg(long, value_name = "PATH", env = "BQRS_CLIENT_SECRET_FILE")]
+        client_secret_file: PathBuf,
+    },
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+enum CacheCommand {
+    /// list the cached results that have not expired, with the bytes each has saved
+    Ls,
+    /// remove every cached result
+    Clear,
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+enum CatalogCommand {
+    /// fetch the catalog of the project again
+    Refresh,
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+enum ConfigCommand {
+    /// print the config file
+    Show,
+    /// print a setting of the active profile, e.g. `project` or `labels.team`
+    Get { key: String },
+    /// change a setting of the active profile, `default_profile` selects the default profile
+    Set { key: String, value: String },
+}
+
+fn label(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| format!("`{}` is not in the form key=value", s))
+}
+
+/// how `query` and `history rerun` run a query and print its results
+#[derive(Debug, Args, PartialEq)]
+struct QueryArgs {
+    /// Defaults to `table` when stdout is a terminal, otherwise `csv`
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+    /// Maximum width of a cell in `table` format before it is truncated
+    #[arg(long, default_value_t = 40)]
+    max_width: usize,
+    /// Text of NULL values in every format, e.g. `--null ''`, defaults to `NULL` and to nothing in `csv` and `tsv`
+    #[arg(long, value_name = "TEXT")]
+    null: Option<String>,
+    /// File to write the results into, required for `parquet` and `arrow`
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Timezone to render timestamps in, e.g. `America/New_York`, defaults to UTC
+    #[arg(long)]
+    timezone: Option<chrono_tz::Tz>,
+    #[arg(long, value_enum, default_value_t = Geography::Wkt)]
+    geography: Geography,
+    /// Summary of the job statistics printed on stderr
+    #[arg(long, value_enum, default_value_t = Stats::None)]
+    stats: Stats,
+    /// Print the query plan on stderr once the query finishes
+    #[arg(long)]
+    explain: bool,
+    /// Run inside a session from `session start`, by name or id
+    #[arg(long)]
+    session: Option<String>,
+    /// Serve the results of a `SELECT` from a local cache for this long, e.g. `30m` or `1d`
+    #[arg(long, value_name = "TTL", value_parser = ttl)]
+    cache: Option<Duration>,
+    #[command(flatten)]
+    csv: CsvArgs,
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+enum HistoryCommand {
+    /// list the most recent queries, oldest first
+    Ls {
+        #[command(flatten)]
+        filter: HistoryFilter,
+    },
+    /// print every detail of a query, by history id or job id
+    Show {
+        entry: String,
+        #[arg(long)]
+        json: bool,
+    },
+    /// run a query from the history again, with the current defaults
+    Rerun {
+        entry: String,
+        #[command(flatten)]
+        args: QueryArgs,
+    },
+    /// list the queries whose text or job id contains `pattern`, ignoring case
+    Search {
+        pattern: String,
+        #[command(flatten)]
+        filter: HistoryFilter,
+    },
+}
+
+#[derive(Debug, Args, PartialEq)]
+struct HistoryFilter {
+    /// Only queries run in this project
+    #[arg(long)]
+    project: Option<String>,
+    /// Only queries run with this profile
+    #[arg(long)]
+    profile: Option<String>,
+    #[arg(long, value_enum)]
+    status: Option<Status>,
+    /// Only queries run on or after this date, `YYYY-MM-DD` or RFC 3339
+    #[arg(long, value_parser = since)]
+    since: Option<i64>,
+    /// Number of most recent queries to list
+    #[arg(short = 'n', long, default_value_t = 20)]
+    limit: usize,
+    /// Print the entries as a JSON array
+    #[arg(long)]
+    json: bool,
+}
+
+/// dialect of the `csv` and `tsv` formats
+#[derive(Debug, Args, PartialEq)]
+struct CsvArgs {
+    /// Field delimiter, use `\t` for tabs
+    #[arg(long)]
+    delimiter: Option<String>,
+    /// Do not write the header row
+    #[arg(long)]
+    no_header: bool,
+    #[arg(long, value_enum, default_value_t = QuoteStyle::Necessary)]
+    quote: QuoteStyle,
+    #[arg(long, value_enum, default_value_t = LineTerminator::Lf)]
+    line_terminator: LineTerminator,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum QuoteStyle {
+    Necessary,
+    Always,
+    NonNumeric,
+    Never,
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+enum JobsCommand {
+    /// render the query plan and slot usage of a job
+    Explain {
+        job_id: String,
+        /// Location of the job, only optional for the `US` and `EU` multi-regions
+        #[arg(short, long)]
+        location: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Stats {
+    Text,
+    Json,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Geography {
+    /// well known text
+    Wkt,
+    Geojson,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum LineTerminator {
+    Lf,
+    Crlf,
+}
+
+impl CsvArgs {
+    fn options(
+        &self,
+        format: Format,
+        values: FormatOptions,
+    ) -> anyhow::Result<bq_rs::query::response::CsvOptions> {
+        use bq_rs::query::response::{CsvOptions, QuoteStyle as Quote};
+
+        let mut options = match format {
+            Format::Tsv => CsvOptions::tsv(),
+            _ => CsvOptions::default(),
+        };
+
+        if let Some(delimiter) = &self.delimiter {
+            let delimiter = delimiter.replace("\\t", "\t");
+            let mut chars = delimiter.chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                anyhow::bail!("delimiter must be a single character");
+            };
+            options.delimiter = c;
+        }
+
+        options.header = !self.no_header;
+        options.quote = match self.quote {
+            QuoteStyle::Necessary => Quote::Necessary,
+            QuoteStyle::Always => Quote::Always,
+            QuoteStyle::NonNumeric => Quote::NonNumeric,
+            QuoteStyle::Never => Quote::Never,
+        };
+        options.line_terminator = match self.line_terminator {
+            LineTerminator::Lf => "\n",
+            LineTerminator::Crlf => "\r\n",
+        }
+        .to_string();
+        options.values = values;
+
+        Ok(options)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum Format {
+    Csv,
+    /// tab separated values
+    Tsv,
+    /// aligned table for reading in a terminal
+    Table,
+    Markdown,
+    Html,
+    /// requires the `parquet` feature
+    Parquet,
+    /// arrow IPC file, requires the `arrow` feature
+    Arrow,
+}
+
+/// safe mode, resolved from `--allow-write`, `--yes` and the profile
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Policy {
+    pub allow_write: bool,
+    /// skips the confirmation of `DROP`, `TRUNCATE` and statements that are only known once they run
+    pub yes: bool,
+}
+
+impl Policy {
+    /// refuses statements that write unless they are allowed, and asks before ones that drop or empty tables
+    /// or whose statements are unknown, e.g. `CALL` or scripts with `EXECUTE IMMEDIATE`
+    pub fn check(
+        &self,
+        client: &bq_rs::api::Client,
+        request: &QueryRequestBuilder,
+    ) -> anyhow::Result<()> {
+        if self.allow_write && self.yes {
+            return Ok(());
+        }
+
+        let statement_type = bq_rs::policy::statement_type(client, request.clone())?;
+        let access = match statement_type.as_deref() {
+            Some("SCRIPT") => bq_rs::policy::script_access(request.clone().build().query()),
+            Some(statement_type) => bq_rs::policy::access(statement_type),
+            None => Access::Unknown,
+        };
+        let statement_type = statement_type.as_deref().unwrap_or("UNKNOWN");
+
+        match access {
+            Access::Read => Ok(()),
+            _ if !self.allow_write => anyhow::bail!(
+                "{} statements may write and are refused in safe mode, pass `--allow-write` or set `allow_write = true` in the profile",
+                statement_type
+            ),
+            Access::Destructive if !self.yes => confirm(&format!(
+                "{} cannot be easily undone",
+                statement_type
+            )),
+            Access::Unknown if !self.yes => confirm(&format!(
+                "{} may run statements that cannot be easily undone",
+                statement_type
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `warning` starts with the statement type, e.g. `DROP_TABLE cannot be easily undone`
+fn confirm(warning: &str) -> anyhow::Result<()> {
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!(
+            "{}, pass `--yes` to run it when there is no terminal",
+            warning
+        );
+    }
+
+    eprint!("{}, run it? [y/N] ", warning);
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        anyhow::bail!("the statement was not run");
+    }
+
+    Ok(())
+}
+
+/// query settings resolved from flags, environment variables and the profile
+pub(crate) struct Defaults {
+    pub location: Option<String>,
+    /// `dataset` or `project.dataset`
+    pub dataset: Option<String>,
+    pub maximum_bytes_billed: Option<u64>,
+    pub labels: HashMap<String, String>,
+}
+
+impl Defaults {
+    pub fn request(&self, query: String, project_id: &str) -> QueryRequestBuilder {
+        let mut request = QueryRequestBuilder::new(query);
+
+        if let Some(location) = &self.location {
+            request = request.location(location.clone());
+        }
+
+        if let Some(dataset) = &self.dataset {
+            let (project, dataset) = dataset.split_once('.').unwrap_or((project_id, dataset));
+            request = request.default_dataset(DatasetReference::new(
+                project.to_string(),
+                dataset.to_string(),
+            ));
+        }
+
+        if let Some(bytes) = self.maximum_bytes_billed {
+            request = request.maximum_bytes_billed(bytes);
+        }
+
+        if !self.labels.is_empty() {
+            request = request.labels(self.labels.clone());
+        }
+
+        request
+    }
 }
 
 impl Cli {
@@ -40,56 +538,1004 @@ impl Cli {
         let (key, project_id, command) = (self.key, self.project_id, self.command);
 
         if command == Commands::Info {
-            let credentials = gauthenticator::from_env();
+            let credentials = gauthenticator::from_env_configuration(self.configuration.as_deref());
             credentials.print();
             return Ok(());
         }
 
+        if let Commands::Config { command } = command {
+            return config(command, self.profile.as_deref());
+        }
+
+        if let Commands::Cache { command } = command {
+            return cache(command);
+        }
+
+        if let Commands::Auth { command } = command {
+            return auth(command, self.scopes);
+        }
+
+        // only rerunning a query needs credentials
+        if let Commands::History {
+            command:
+                command @ (HistoryCommand::Ls { .. }
+                | HistoryCommand::Show { .. }
+                | HistoryCommand::Search { .. }),
+        } = command
+        {
+            return history(command);
+        }
+
+        // flags and environment variables take priority over the profile
+        let config = Config::load()?;
+        let profile_name = config.profile_name(self.profile.as_deref()).to_string();
+        let profile = config.profile(self.profile.as_deref())?;
+        let mut labels: HashMap<String, String> = profile.labels.into_iter().collect();
+        labels.extend(self.labels);
+        let defaults = Defaults {
+            location: self.location.or(profile.location),
+            dataset: self.dataset.or(profile.dataset),
+            maximum_bytes_billed: self.maximum_bytes_billed.or(profile.max_bytes_billed),
+            labels,
+        };
+        let policy = Policy {
+            allow_write: self.allow_write || profile.allow_write.unwrap_or(false),
+            yes: self.yes,
+        };
+        let catalog_ttl = profile
+            .catalog_ttl
+            .map(Duration::from_secs)
+            .unwrap_or(CATALOG_TTL);
+        let default_format = profile
+            .format
+            .as_deref()
+            .map(|f| {
+                Format::from_str(f, true).map_err(|e| anyhow::anyhow!("invalid format: {}", e))
+            })
+            .transpose()?;
+
         // tries loading from key if provided
         // otherwise will trying loading from environment
-        let authentication = match key {
-            Some(path) => Some(gauthenticator::from_file(path)),
-            None => gauthenticator::from_env().authentication(),
+        let env = gauthenticator::from_env_configuration(self.configuration.as_deref());
+        let gcloud_project = env.gcloud_configuration().and_then(|c| c.project.clone());
+        // `GOOGLE_APPLICATION_CREDENTIALS` is an environment variable, so it beats the profile
+        let (profile_key, profile_credentials) =
+            match std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS") {
+                Some(_) => (None, None),
+                None => (profile.key, profile.credentials),
+            };
+        let authentication = match (key.or(profile_key), profile_credentials) {
+            (Some(path), _) if path.extension().is_some_and(|e| e == "p12") => {
+                let Some(email) = &self.service_account_email else {
+                    anyhow::bail!("`.p12` keys need the `--service-account-email` they belong to");
+                };
+                Some(gauthenticator::from_pkcs12_file(path, email))
+            }
+            (Some(path), _) => Some(gauthenticator::from_file(path)),
+            (None, Some(CredentialSource::Env)) => Some(env.google_application_credentials()),
+            (None, Some(CredentialSource::Gcloud)) => env.core_profile(),
+            (None, Some(CredentialSource::ApplicationDefault)) => env.application_default(),
+            (None, Some(CredentialSource::Metadata)) => {
+                Some(gauthenticator::from_metadata_server())
+            }
+            (None, None) => env.authentication(),
         };
 
         let Some(authentication) = authentication else {
             panic!("failed to find credentials");
         };
 
+        // the last account of the chain is impersonated, the ones before it are delegates
+        let authentication = match self
+            .impersonate_service_account
+            .or(profile.impersonate_service_account)
+        {
+            Some(chain) => {
+                let mut delegates: Vec<String> =
+                    chain.split(',').map(|e| e.trim().to_string()).collect();
+                let target = delegates.pop().unwrap_or_default();
+                authentication.impersonate(
+                    &target,
+                    &delegates,
+                    self.impersonation_lifetime
+                        .or(profile.impersonation_lifetime),
+                )
+            }
+            None => authentication,
+        };
+
         log::debug!("{}", authentication.message());
 
-        // load project id from user input or from the service account file
+        // an ID token needs neither the project nor an access token
+        if let Commands::Token {
+            audience: Some(audience),
+            id_token: true,
+        } = command
+        {
+            let response = authentication.request_token(&TokenRequest::IdToken { audience })?;
+            println!("{}", response.id_token.unwrap_or_default());
+            return Ok(());
+        }
+
+        // load project id from user input, the profile, the credentials or the gcloud configuration
         let project_id = project_id
-            .as_deref()
-            .or(authentication.project_id())
+            .or(profile.project)
+            .or(authentication.project_id().map(String::from))
+            .or(gcloud_project)
             .expect("project id is required");
+        let project_id = project_id.as_str();
 
-        let token = authentication.token(None)?;
-        let client = bq_rs::api::Client::bq_client(token, project_id);
+        // a service account signs its own token unless scopes or a subject need an exchange
+        let scopes = if self.scopes.is_empty() {
+            profile.scopes
+        } else {
+            self.scopes
+        };
+        let token_request = match (scopes.is_empty(), self.subject.or(profile.subject)) {
+            (true, None) => TokenRequest::default(),
+            (true, subject) => TokenRequest::Exchange {
+                scopes: vec![CLOUD_PLATFORM_SCOPE.to_string()],
+                subject,
+            },
+            (false, subject) => TokenRequest::Exchange { scopes, subject },
+        };
+        let token = authentication.request_token(&token_request)?.access_token;
+        let client = bq_rs::api::Client::bq_client(token.clone(), project_id);
 
         match command {
-            Commands::Info => {}
-            Commands::Query { query, format } => {
-                let request = bq_rs::query::request::QueryRequestBuilder::new(query).build();
-                let query_response = client.jobs_query(request);
-
-                match format.as_deref() {
-                    Some("csv") => println!("{}", query_response.into_csv()),
-                    // this is not ready
-                    // Some("json") => println!("{}", query_response.into_json()),
-                    // default to csv output
-                    _ => println!("{}", query_response.into_csv()),
+            Commands::Info
+            | Commands::Config { .. }
+            | Commands::Cache { .. }
+            | Commands::Auth { .. } => {}
+            Commands::Shell { format, null } => {
+                let catalog = catalog(&client, project_id, &defaults, catalog_ttl, false)?;
+                let history = Config::data_dir()?.join("shell_history");
+                Shell::new(
+                    token.clone(),
+                    project_id.to_string(),
+                    defaults,
+                    policy,
+                    format.or(default_format).unwrap_or(Format::Table),
+                    null,
+                    catalog,
+                )
+                .run(Some(history))?;
+            }
+            Commands::Head {
+                table,
+                rows,
+                format,
+                null,
+            } => {
+                let Some(reference) = TableReference::parse(&table, project_id) else {
+                    anyhow::bail!("expected `dataset.table` or `project.dataset.table`");
+                };
+
+                let data = client.tabledata_list(&reference, rows)?;
+                let response = data.into_response(client.tables_get_reference(&reference)?);
+                let format =
+                    format
+                        .or(default_format)
+                        .unwrap_or(if std::io::stdout().is_terminal() {
+                            Format::Table
+                        } else {
+                            Format::Csv
+                        });
+
+                print!(
+                    "{}",
+                    line_ended(render(
+                        response,
+                        format,
+                        FormatOptions::default(),
+                        null.as_deref()
+                    ))
+                );
+            }
+            Commands::Session { command } => match command {
+                SessionCommand::Start { name } => {
+                    let session = Session::start(&client, defaults.location)?;
+                    let now = chrono::Utc::now().timestamp();
+
+                    let mut sessions = Sessions::load()?;
+                    sessions.add(Saved {
+                        id: session.id().to_string(),
+                        name,
+                        project_id: project_id.to_string(),
+                        location: session.location().to_string(),
+                        started_at: now,
+                        last_used_at: now,
+                    })?;
+                    sessions.save()?;
+
+                    println!("{}", session.id());
                 }
+                SessionCommand::End { session } => {
+                    let mut sessions = Sessions::load()?;
+                    let saved = sessions.get(&session, project_id)?;
+
+                    Session::attach(&client, saved.id.clone(), saved.location).end()?;
+                    sessions.remove(&saved.id);
+                    sessions.save()?;
+                }
+                SessionCommand::List => {
+                    let format = |secs: i64| {
+                        chrono::DateTime::from_timestamp(secs, 0)
+                            .map(|t| t.to_rfc3339())
+                            .unwrap_or_default()
+                    };
+
+                    for saved in Sessions::load()?.sessions {
+                        println!(
+                            "{}\t{}\t{}\t{}\tstarted {}\tlast used {}",
+                            saved.name.as_deref().unwrap_or("-"),
+                            saved.id,
+                            saved.project_id,
+                            saved.location,
+                            format(saved.started_at),
+                            format(saved.last_used_at)
+                        );
+                    }
+                }
+            },
+            Commands::Catalog { command } => match command {
+                CatalogCommand::Refresh => {
+                    let catalog = catalog(&client, project_id, &defaults, catalog_ttl, true)?;
+                    let tables: usize = catalog.datasets.values().map(|d| d.len()).sum();
+                    println!(
+                        "{} datasets and {} tables in {}",
+                        catalog.datasets.len(),
+                        tables,
+                        project_id
+                    );
+                }
+            },
+            Commands::Query { query, args } => {
+                let request = defaults
+                    .request(query.clone(), project_id)
+                    .use_int64_timestamp();
+
+                let parameters = request.parameters().to_vec();
+                Entry::new(profile_name, project_id.to_string(), query, parameters).record(
+                    |entry| self::query(&client, request, args, default_format, policy, entry),
+                )?;
             }
-            Commands::Token { audience } => {
+            Commands::History {
+                command: HistoryCommand::Rerun { entry, args },
+            } => {
+                let previous = History::open()?.get(&entry)?;
+                if previous.project_id != project_id {
+                    eprintln!(
+                        "note: the query ran in project {} before, it now runs in {}",
+                        previous.project_id, project_id
+                    );
+                }
+
+                let mut request = defaults
+                    .request(previous.query.clone(), project_id)
+                    .use_int64_timestamp();
+                if !previous.parameters.is_empty() {
+                    request = request.query_parameters(previous.parameters.clone());
+                }
+
+                Entry::new(
+                    profile_name,
+                    project_id.to_string(),
+                    previous.query,
+                    request.parameters().to_vec(),
+                )
+                .record(|entry| {
+                    self::query(&client, request, args, default_format, policy, entry)
+                })?;
+            }
+            Commands::History { .. } => {}
+            Commands::Jobs { command } => match command {
+                JobsCommand::Explain {
+                    job_id,
+                    location: job_location,
+                } => {
+                    let job =
+                        client.jobs_get(&job_id, job_location.or(defaults.location).as_deref())?;
+                    println!("{}", bq_rs::explain::render(&job));
+                }
+            },
+            Commands::Token { audience: None, .. } => println!("{}", token),
+            Commands::Token { audience, .. } => {
                 let token = authentication.token(audience)?;
                 println!("{}", token);
             }
             Commands::DatasetList { id } => {
-                println!("{}", client.tables_list(&id).into_string()?);
+                println!("{}", client.tables_list(&id)?.into_string()?);
             }
         };
 
         Ok(())
     }
 }
+
+/// runs a query for `query` and `history rerun`, filling in the job of the history entry
+fn query(
+    client: &bq_rs::api::Client,
+    mut request: QueryRequestBuilder,
+    args: QueryArgs,
+    default_format: Option<Format>,
+    policy: Policy,
+    entry: &mut Entry,
+) -> anyhow::Result<()> {
+    let QueryArgs {
+        format,
+        max_width,
+        null,
+        output,
+        timezone,
+        geography,
+        stats,
+        explain,
+        session,
+        cache,
+        csv,
+    } = args;
+
+    let in_session = session.is_some();
+    if let Some(session) = session {
+        let mut sessions = Sessions::load()?;
+        let saved = sessions.get(&session, &entry.project_id)?;
+        sessions.touch(&saved.id);
+        sessions.save()?;
+        request = request.session_id(saved.id).location(saved.location);
+    }
+
+    let format = format
+        .or(default_format)
+        .unwrap_or(if std::io::stdout().is_terminal() {
+            Format::Table
+        } else {
+            Format::Csv
+        });
+
+    let values = FormatOptions {
+        timezone,
+        geography: match geography {
+            Geography::Wkt => GeographyFormat::Wkt,
+            Geography::Geojson => GeographyFormat::GeoJson,
+        },
+    };
+
+    let mut csv = csv.options(format, values.clone())?;
+    if let Some(null) = &null {
+        csv.null = null.clone();
+    }
+    let null = null.unwrap_or_else(|| bq_rs::render::NULL.to_string());
+    let table = bq_rs::render::TableOptions {
+        max_width,
+        values: values.clone(),
+        null: null.clone(),
+        // piped tables stay free of escape codes
+        dim_nulls: output.is_none() && std::io::stdout().is_terminal(),
+    };
+    let render = |response: QueryResponse| match format {
+        Format::Csv | Format::Tsv => response.into_csv_with(&csv),
+        Format::Markdown => response.into_markdown(&values, &null),
+        Format::Html => response.into_html(&values, &null),
+        Format::Table | Format::Parquet | Format::Arrow => response.into_table(&table),
+    };
+
+    if bq_rs::script::is_script(&entry.query) {
+        if matches!(format, Format::Parquet | Format::Arrow) {
+            anyhow::bail!("scripts can only be written in text formats");
+        }
+
+        policy.check(client, &request)?;
+        let job = bq_rs::script::wait(client, client.jobs_insert(&request.build())?)?;
+        entry.job_id = job.job_reference.job_id.clone();
+        entry.location = Some(job.job_reference.location.clone());
+        entry.bytes_billed = bytes_billed(&job);
+
+        let (text, failure) = script(client, &job, stats, explain, render)?;
+        match output {
+            Some(path) => std::fs::write(path, text)?,
+            None => print!("{}", text),
+        }
+
+        if let Some(failure) = failure {
+            anyhow::bail!("{}", failure);
+        }
+        return Ok(());
+    }
+
+    let builder = request;
+    let request = builder.clone().build();
+    let write = |response: QueryResponse| -> anyhow::Result<()> {
+        let text = match format {
+            // this is not ready
+            // Format::Json => println!("{}", query_response.into_json()),
+            Format::Parquet | Format::Arrow => {
+                let Some(path) = output else {
+                    anyhow::bail!("`--output` is required for parquet and arrow formats");
+                };
+                return export(client, response, &entry.query, format, path);
+            }
+            _ => line_ended(render(response)),
+        };
+
+        match output {
+            Some(path) => std::fs::write(path, text)?,
+            None => print!("{}", text),
+        }
+        Ok(())
+    };
+
+    // temporary tables of a session are not part of the key
+    let cache = match cache {
+        Some(ttl) if !in_session => Some((
+            Cache::new(Config::cache_dir()?.join("results")),
+            bq_rs::cache::key(&request, &entry.project_id),
+            ttl,
+        )),
+        _ => None,
+    };
+
+    if let Some((cache, key, _)) = &cache {
+        if let Some(mut cached) = cache.get(client, key) {
+            cache.hit(&mut cached)?;
+            entry.job_id = cached.response.job_reference.job_id.clone();
+            entry.location = Some(cached.response.job_reference.location.clone());
+            entry.bytes_billed = Some(0);
+
+            match stats {
+                Stats::None => {}
+                Stats::Json => eprintln!(
+                    "{}",
+                    serde_json::json!({
+                        "local_cache_hit": true,
+                        "job_id": entry.job_id,
+                        "bytes_saved": cached.bytes_billed,
+                    })
+                ),
+                Stats::Text => eprintln!(
+                    "served from the local cache, saving {}",
+                    bq_rs::job::bytes(cached.bytes_billed)
+                ),
+            }
+            return write(cached.response);
+        }
+    }
+
+    // cached results only ever come from `SELECT` queries, so only queries that run are checked
+    policy.check(client, &builder)?;
+    let mut query_response = client.jobs_query(request)?;
+    // the statistics of the job take another request, only made when something uses them
+    let needs_job = stats != Stats::None || explain || cache.is_some();
+    let job = match query_response.job_reference.job_id.as_deref() {
+        Some(id) if needs_job => {
+            match client.jobs_get(id, Some(&query_response.job_reference.location)) {
+                Ok(job) => Some(job),
+                Err(e) => {
+                    log::warn!("failed to read the statistics of {}: {}", id, e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    entry.job_id = query_response.job_reference.job_id.clone();
+    entry.location = Some(query_response.job_reference.location.clone());
+    entry.bytes_billed = job.as_ref().and_then(bytes_billed);
+
+    // stdout is kept clean for piping, the summary and plan go to stderr
+    if explain {
+        if let Some(job) = &job {
+            eprintln!("{}", bq_rs::explain::render(job));
+        }
+    }
+    print_stats(stats, &Summary::new(&query_response, job.as_ref()))?;
+
+    // only complete results of queries that change nothing are kept
+    if let (Some((cache, key, ttl)), Some(job)) = (cache, &job) {
+        let statement_type = job
+            .statistics
+            .as_ref()
+            .and_then(|s| s.query.as_ref())
+            .and_then(|q| q.statement_type.as_deref());
+        if statement_type == Some("SELECT")
+            && query_response.page_token.is_none()
+            && bq_rs::cache::is_deterministic(&entry.query)
+        {
+            match bq_rs::cache::sources(client, job) {
+                Ok(sources) => {
+                    let cached =
+                        Cached::new(key, entry.query.clone(), ttl, job, sources, query_response);
+                    if let Err(e) = cache.put(&cached) {
+                        log::warn!("failed to cache the results: {}", e);
+                    }
+                    query_response = cached.response;
+                }
+                Err(e) => log::warn!("failed to read the tables of the cached results: {}", e),
+            }
+        }
+    }
+
+    write(query_response)
+}
+
+/// prints the summary of a query to stderr
+fn print_stats(stats: Stats, summary: &Summary) -> anyhow::Result<()> {
+    match stats {
+        Stats::None => {}
+        Stats::Json => eprintln!("{}", serde_json::to_string(summary)?),
+        Stats::Text => eprintln!("{}", summary),
+    }
+    Ok(())
+}
+
+fn bytes_billed(job: &Job) -> Option<u64> {
+    let query = job.statistics.as_ref()?.query.as_ref()?;
+    query.total_bytes_billed.as_deref()?.parse().ok()
+}
+
+/// renders the rows of each `SELECT` of a finished script in the order they ran
+///
+/// statements after a failed one do not run, the failure is returned with the text of those before it
+fn script(
+    client: &bq_rs::api::Client,
+    job: &Job,
+    stats: Stats,
+    explain: bool,
+    render: impl Fn(QueryResponse) -> String,
+) -> anyhow::Result<(String, Option<Failure>)> {
+    let statements = bq_rs::script::statements(client, job)?;
+
+    let mut texts = Vec::new();
+    for (i, statement) in statements.iter().enumerate() {
+        if statement.error().is_some() {
+            break;
+        }
+
+        let statement_type = statement
+            .statistics
+            .as_ref()
+            .and_then(|s| s.query.as_ref())
+            .and_then(|q| q.statement_type.as_deref())
+            .unwrap_or("UNKNOWN");
+        let position = bq_rs::script::position(statement)
+            .map(|(line, column)| format!(" at line {}, column {}", line, column))
+            .unwrap_or_default();
+        eprintln!("-- statement {}{}: {}", i + 1, position, statement_type);
+
+        if explain {
+            eprintln!("{}", bq_rs::explain::render(statement));
+        }
+
+        let is_select = statement_type == "SELECT";
+        if stats == Stats::None && !is_select {
+            continue;
+        }
+
+        let Some(job_id) = &statement.job_reference.job_id else {
+            continue;
+        };
+        let response = client.jobs_query_results(job_id, &statement.job_reference.location)?;
+        print_stats(stats, &Summary::new(&response, Some(statement)))?;
+        if is_select {
+            texts.push(line_ended(render(response)));
+        }
+    }
+
+    Ok((texts.join("\n"), bq_rs::script::failure(job, &statements)))
+}
+
+/// ends text with a line break, csv and tsv already end every record with their line terminator
+pub(crate) fn line_ended(mut text: String) -> String {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+/// renders the text formats with their default options, `parquet` and `arrow` fall back to `table`
+/// `null` is the text of NULL values, `None` keeps the default of the format
+pub(crate) fn render(
+    response: QueryResponse,
+    format: Format,
+    values: FormatOptions,
+    null: Option<&str>,
+) -> String {
+    use bq_rs::{query::response::CsvOptions, render::TableOptions};
+
+    match format {
+        Format::Csv | Format::Tsv => {
+            let mut options = match format {
+                Format::Tsv => CsvOptions::tsv(),
+                _ => CsvOptions::default(),
+            };
+            options.values = values;
+            if let Some(null) = null {
+                options.null = null.to_string();
+            }
+            response.into_csv_with(&options)
+        }
+        Format::Markdown => response.into_markdown(&values, null.unwrap_or(bq_rs::render::NULL)),
+        Format::Html => response.into_html(&values, null.unwrap_or(bq_rs::render::NULL)),
+        Format::Table | Format::Parquet | Format::Arrow => response.into_table(&TableOptions {
+            values,
+            null: null.unwrap_or(bq_rs::render::NULL).to_string(),
+            dim_nulls: std::io::stdout().is_terminal(),
+            ..TableOptions::default()
+        }),
+    }
+}
+
+/// loads the cached catalog of the project, fetching it again once it is older than `ttl`
+///
+/// a partial catalog is used but not saved, so that it is fetched again next time
+fn catalog(
+    client: &bq_rs::api::Client,
+    project_id: &str,
+    defaults: &Defaults,
+    ttl: Duration,
+    refresh: bool,
+) -> anyhow::Result<Catalog> {
+    let path = Config::catalog_path(project_id)?;
+
+    if let Some(catalog) = Catalog::load(&path).filter(|c| !refresh && !c.is_stale(ttl)) {
+        return Ok(catalog);
+    }
+
+    eprintln!("fetching the catalog of {}…", project_id);
+    let (catalog, failures) = Catalog::fetch(
+        client,
+        project_id,
+        &defaults.labels,
+        defaults.maximum_bytes_billed,
+    );
+    if failures.is_empty() {
+        catalog.save(&path)?;
+    }
+    for failure in failures {
+        eprintln!("warning: the catalog is incomplete: {}", failure);
+    }
+    Ok(catalog)
+}
+
+/// the cached catalog of the active project, completions never go over the network
+///
+/// the project is resolved like `Cli::run` does, from the command line being completed, the
+/// profile and the gcloud configuration, only the project of the credentials is not read
+fn cached_catalog() -> Option<Catalog> {
+    let args: Vec<String> = std::env::args().collect();
+    let flag = |names: &[&str], variable: &str| {
+        flag_value(&args, names).or_else(|| std::env::var(variable).ok())
+    };
+
+    let project_id = match flag(&["-p", "--project-id"], "BQRS_PROJECT") {
+        Some(project_id) => project_id,
+        None => {
+            let profile = flag(&["--profile"], "BQRS_PROFILE");
+            let configuration = flag_value(&args, &["--configuration"]);
+            Config::load()
+                .ok()
+                .and_then(|config| config.profile(profile.as_deref()).ok())
+                .and_then(|profile| profile.project)
+                .or_else(|| {
+                    gauthenticator::from_env_configuration(configuration.as_deref())
+                        .gcloud_configuration()
+                        .and_then(|c| c.project.clone())
+                })?
+        }
+    };
+
+    Catalog::load(&Config::catalog_path(&project_id).ok()?)
+}
+
+/// the value of a flag on a command line, given as `--flag value`, `--flag=value` or `-fvalue`
+fn flag_value(args: &[String], names: &[&str]) -> Option<String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        for name in names {
+            if arg == name {
+                return args.next().cloned();
+            }
+            let value = match name.strip_prefix("--") {
+                Some(_) => arg.strip_prefix(name).and_then(|v| v.strip_prefix('=')),
+                None => arg.strip_prefix(name).filter(|v| !v.is_empty()),
+            };
+            if let Some(value) = value {
+                return Some(value.to_string());
+            }
+        }
+    }
+    None
+}
+
+fn complete_table(current: &OsStr) -> Vec<CompletionCandidate> {
+    let (Some(current), Some(catalog)) = (current.to_str(), cached_catalog()) else {
+        return Vec::new();
+    };
+
+    catalog
+        .complete_table(current)
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+fn complete_dataset(current: &OsStr) -> Vec<CompletionCandidate> {
+    let (Some(current), Some(catalog)) = (current.to_str(), cached_catalog()) else {
+        return Vec::new();
+    };
+
+    catalog
+        .datasets
+        .keys()
+        .filter(|d| d.starts_with(current))
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// a number followed by `s`, `m`, `h` or `d`
+fn ttl(value: &str) -> Result<Duration, String> {
+    let unit = match value.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        _ => return Err("expected a unit of `s`, `m`, `h` or `d`, e.g. `30m`".to_string()),
+    };
+
+    let number: u64 = value[..value.len() - 1]
+        .parse()
+        .map_err(|e| format!("{}", e))?;
+    let seconds = number
+        .checked_mul(unit)
+        .ok_or_else(|| format!("{} is too long", value))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+fn auth(command: AuthCommand, scopes: Vec<String>) -> anyhow::Result<()> {
+    let AuthCommand::Login {
+        no_browser,
+        output,
+        client_secret_file,
+    } = command;
+
+    let mut login = gauthenticator::Login::from_client_secrets(client_secret_file)?;
+    if !scopes.is_empty() {
+        login.scopes = scopes;
+    }
+
+    let (code, redirect_uri) = if no_browser {
+        // nothing listens on the loopback address, the browser is likely on another machine
+        let redirect_uri = "http://127.0.0.1:8085/".to_string();
+        println!(
+            "sign in on\n\n    {}\n\nthe page it redirects to fails to load, paste its url here:",
+            login.authorization_url(&redirect_uri)
+        );
+        let mut pasted = String::new();
+        std::io::stdin().read_line(&mut pasted)?;
+        (login.code_from_redirect(&pasted)?, redirect_uri)
+    } else {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let redirect_uri = format!("http://{}/", listener.local_addr()?);
+        let url = login.authorization_url(&redirect_uri);
+        println!("sign in on\n\n    {}\n", url);
+        if let Err(e) = open_browser(&url) {
+            log::warn!("failed to open a browser: {}", e);
+        }
+        (login.wait_for_redirect(&listener)?, redirect_uri)
+    };
+
+    let credentials = login.exchange(&code, &redirect_uri)?;
+    let path = match output {
+        Some(path) => path,
+        None => gauthenticator::application_default_credentials()?,
+    };
+    gauthenticator::save_credentials(&credentials, &path)?;
+    println!("credentials saved to {}", path.display());
+    Ok(())
+}
+
+fn open_browser(url: &str) -> std::io::Result<std::process::ExitStatus> {
+    if cfg!(target_os = "macos") {
+        std::process::Command::new("open").arg(url).status()
+    } else if cfg!(windows) {
+        // unlike `cmd /C start`, which splits the url at each `&` into commands of its own
+        std::process::Command::new("rundll32")
+            .args(["url.dll,FileProtocolHandler", url])
+            .status()
+    } else {
+        std::process::Command::new("xdg-open").arg(url).status()
+    }
+}
+
+fn cache(command: CacheCommand) -> anyhow::Result<()> {
+    let cache = Cache::new(Config::cache_dir()?.join("results"));
+
+    match command {
+        CacheCommand::Ls => {
+            let now = chrono::Utc::now().timestamp();
+            for cached in cache.entries()? {
+                let query: Vec<&str> = cached.query.split_whitespace().collect();
+                println!(
+                    "{}\t{}\tcached {}m ago\texpires in {}m\t{} hits\tsaved {}\t{}",
+                    &cached.key[..12],
+                    cached.project_id,
+                    (now - cached.cached_at) / 60,
+                    (cached.expires_at - now) / 60,
+                    cached.hits,
+                    bq_rs::job::bytes(cached.hits * cached.bytes_billed),
+                    query.join(" ")
+                );
+            }
+
+            let totals = cache.totals();
+            println!(
+                "{} hits have saved {} in total",
+                totals.hits,
+                bq_rs::job::bytes(totals.bytes_saved)
+            );
+        }
+        CacheCommand::Clear => println!("removed {} cached results", cache.clear()?),
+    }
+
+    Ok(())
+}
+
+/// `YYYY-MM-DD` is midnight in UTC
+fn since(value: &str) -> Result<i64, String> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_time(chrono::NaiveTime::MIN).and_utc().timestamp());
+    }
+
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|t| t.timestamp())
+        .map_err(|e| e.to_string())
+}
+
+fn history(command: HistoryCommand) -> anyhow::Result<()> {
+    let history = History::open()?;
+
+    let (filter, pattern) = match command {
+        HistoryCommand::Ls { filter } => (filter, None),
+        HistoryCommand::Search { pattern, filter } => (filter, Some(pattern)),
+        HistoryCommand::Show { entry, json } => {
+            let entry = history.get(&entry)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&entry)?);
+            } else {
+                println!("{:#}", entry);
+            }
+            return Ok(());
+        }
+        HistoryCommand::Rerun { .. } => unreachable!("rerunning a query needs credentials"),
+    };
+
+    let HistoryFilter {
+        project,
+        profile,
+        status,
+        since,
+        limit,
+        json,
+    } = filter;
+    let filter = Filter {
+        project_id: project,
+        profile,
+        status,
+        since,
+        pattern,
+    };
+
+    let mut entries: Vec<Entry> = history
+        .entries()?
+        .into_iter()
+        .filter(|entry| filter.matches(entry))
+        .collect();
+    let entries = entries.split_off(entries.len().saturating_sub(limit));
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        for entry in entries {
+            println!("{}", entry);
+        }
+    }
+
+    Ok(())
+}
+
+fn config(command: ConfigCommand, profile: Option<&str>) -> anyhow::Result<()> {
+    let mut editor = Editor::open()?;
+    let config = Config::load()?;
+    let profile = config.profile_name(profile);
+
+    match command {
+        ConfigCommand::Show => {
+            println!("# {}", Config::path()?.display());
+            print!("{}", editor.show());
+        }
+        ConfigCommand::Get { key } => match editor.get(profile, &key) {
+            Some(toml::Value::String(value)) => println!("{}", value),
+            Some(value) => println!("{}", value),
+            None => anyhow::bail!("`{}` is not set in profile `{}`", key, profile),
+        },
+        ConfigCommand::Set { key, value } => {
+            editor.set(profile, &key, &value)?;
+            editor.save()?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "arrow")]
+fn export(
+    client: &bq_rs::api::Client,
+    response: bq_rs::query::response::QueryResponse,
+    query: &str,
+    format: Format,
+    path: PathBuf,
+) -> anyhow::Result<()> {
+    use bq_rs::export::FileFormat;
+
+    let file_format = match format {
+        Format::Arrow => FileFormat::Arrow,
+        #[cfg(feature = "parquet")]
+        Format::Parquet => FileFormat::Parquet,
+        _ => anyhow::bail!(
+            "bq-rs was built without support for `--format {:?}`",
+            format
+        ),
+    };
+
+    let metadata = bq_rs::export::job_metadata(&response, query);
+    let writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+    let rows = bq_rs::export::write(
+        writer,
+        file_format,
+        response.into_record_batches(client),
+        metadata,
+    )?;
+
+    log::info!("wrote {} rows to {}", rows, path.display());
+    Ok(())
+}
+
+#[cfg(not(feature = "arrow"))]
+fn export(
+    _client: &bq_rs::api::Client,
+    _response: bq_rs::query::response::QueryResponse,
+    _query: &str,
+    format: Format,
+    _path: PathBuf,
+) -> anyhow::Result<()> {
+    anyhow::bail!(
+        "bq-rs was built without support for `--format {:?}`",
+        format
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::flag_value;
+
+    #[test]
+    fn reads_flags_of_the_completed_command_line() {
+        let args = |line: &str| line.split(' ').map(String::from).collect::<Vec<_>>();
+        let project = |line: &str| flag_value(&args(line), &["-p", "--project-id"]);
+
+        assert_eq!(
+            project("bq-rs -- bq-rs -p other head sa").as_deref(),
+            Some("other")
+        );
+        assert_eq!(
+            project("bq-rs -- bq-rs --project-id=other head").as_deref(),
+            Some("other")
+        );
+        assert_eq!(
+            project("bq-rs -- bq-rs -pother head").as_deref(),
+            Some("other")
+        );
+        assert_eq!(project("bq-rs -- bq-rs --profile prod head"), None);
+        assert_eq!(project("bq-rs -- bq-rs --project-idx=other head"), None);
+    }
+}
@@ -0,0 +1,382 @@
+//! persistent configuration at `<user_config>/bq-rs/config.toml`
+//!
+//! ```toml
+//! default_profile = "dev"
+//!
+//! [profiles.dev]
+//! key = "/path/to/key.json"
+//! project = "my-project"
+//! location = "US"
+//! dataset = "my_dataset"
+//! format = "table"
+//! max_bytes_billed = 1000000000
+//! catalog_ttl = 86400
+//! allow_write = false
+//!
+//! [profiles.dev.labels]
+//! team = "analytics"
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// the profile used when neither `--profile`, `BQRS_PROFILE` nor `default_profile` are set
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Config {
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Profile {
+    /// path to a credentials file, takes priority over `credentials`
+    pub key: Option<PathBuf>,
+    pub credentials: Option<CredentialSource>,
+    /// OAuth2 scopes to exchange a service account key for instead of signing its own token
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+    /// user to act as with domain-wide delegation
+    pub subject: Option<String>,
+    /// service account to act as, with a comma separated chain of delegates before it
+    pub impersonate_service_account: Option<String>,
+    /// seconds impersonated tokens are valid for
+    pub impersonation_lifetime: Option<u64>,
+    pub project: Option<String>,
+    pub location: Option<String>,
+    /// `dataset` or `project.dataset`
+    pub dataset: Option<String>,
+    pub format: Option<String>,
+    pub max_bytes_billed: Option<u64>,
+    /// seconds before the catalog used for completions is fetched again
+    pub catalog_ttl: Option<u64>,
+    /// statements that write are refused unless this is set, see `Policy`
+    pub allow_write: Option<bool>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// where to load credentials from when no key is given
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CredentialSource {
+    /// `GOOGLE_APPLICATION_CREDENTIALS`
+    Env,
+    /// the account of the active gcloud configuration
+    Gcloud,
+    /// `application_default_credentials.json`
+    ApplicationDefault,
+    /// the service account attached to the GCE instance, Cloud Run service or GKE workload
+    Metadata,
+}
+
+impl Config {
+    /// `BQRS_CONFIG` overrides the location of the file
+    pub fn path() -> anyhow::Result<PathBuf> {
+        if let Ok(path) = std::env::var("BQRS_CONFIG") {
+            return Ok(PathBuf::from(path));
+        }
+
+        let mut path = if cfg!(windows) {
+            PathBuf::from(var("APPDATA", "BQRS_CONFIG")?)
+        } else {
+            let mut home = PathBuf::from(var("HOME", "BQRS_CONFIG")?);
+            home.push(".config");
+            home
+        };
+
+        path.push("bq-rs");
+        path.push("config.toml");
+        Ok(path)
+    }
+
+    /// `BQRS_CACHE_DIR` overrides the directory for cached data
+    pub fn cache_dir() -> anyhow::Result<PathBuf> {
+        if let Ok(path) = std::env::var("BQRS_CACHE_DIR") {
+            return Ok(PathBuf::from(path));
+        }
+
+        let mut path = if cfg!(windows) {
+            PathBuf::from(var("LOCALAPPDATA", "BQRS_CACHE_DIR")?)
+        } else if let Ok(cache) = std::env::var("XDG_CACHE_HOME") {
+            PathBuf::from(cache)
+        } else {
+            let mut home = PathBuf::from(var("HOME", "BQRS_CACHE_DIR")?);
+            home.push(".cache");
+            home
+        };
+
+        path.push("bq-rs");
+        Ok(path)
+    }
+
+    /// `BQRS_DATA_DIR` overrides the directory for data that is kept, like the query history
+    pub fn data_dir() -> anyhow::Result<PathBuf> {
+        if let Ok(path) = std::env::var("BQRS_DATA_DIR") {
+            return Ok(PathBuf::from(path));
+        }
+
+        let mut path = if cfg!(windows) {
+            PathBuf::from(var("LOCALAPPDATA", "BQRS_DATA_DIR")?)
+        } else if let Ok(data) = std::env::var("XDG_DATA_HOME") {
+            PathBuf::from(data)
+        } else {
+            let mut home = PathBuf::from(var("HOME", "BQRS_DATA_DIR")?);
+            home.push(".local");
+            home.push("share");
+            home
+        };
+
+        path.push("bq-rs");
+        Ok(path)
+    }
+
+    /// where the catalog of a project is cached
+    pub fn catalog_path(project_id: &str) -> anyhow::Result<PathBuf> {
+        let mut path = Self::cache_dir()?;
+        path.push("catalog");
+        path.push(format!("{}.json", project_id));
+        Ok(path)
+    }
+
+    /// a missing file, or no home directory to find it in, is treated as an empty configuration
+    pub fn load() -> anyhow::Result<Self> {
+        // containers and CI jobs often run without `HOME`
+        let path = match Self::path() {
+            Ok(path) => path,
+            Err(e) => {
+                log::debug!("no config file: {}", e);
+                return Ok(Self::default());
+            }
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents)
+                .map_err(|e| anyhow::anyhow!("invalid config {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(anyhow::anyhow!("cannot read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    /// resolves the active profile, explicitly requested profiles must exist
+    pub fn profile(&self, name: Option<&str>) -> anyhow::Result<Profile> {
+        if let Some(name) = name {
+            return self
+                .profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("profile `{}` does not exist", name));
+        }
+
+        Ok(self
+            .profiles
+            .get(self.profile_name(None))
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    pub fn profile_name<'a>(&'a self, name: Option<&'a str>) -> &'a str {
+        name.or(self.default_profile.as_deref())
+            .unwrap_or(DEFAULT_PROFILE)
+    }
+}
+
+/// the directory in `name`, or an error that says which variable to set instead
+fn var(name: &str, instead: &str) -> anyhow::Result<String> {
+    std::env::var(name).map_err(|_| anyhow::anyhow!("{} is not set, set {} instead", name, instead))
+}
+
+/// reads and edits the raw file, so settings of other profiles are left untouched
+pub struct Editor {
+    path: PathBuf,
+    table: toml::Table,
+}
+
+impl Editor {
+    pub fn open() -> anyhow::Result<Self> {
+        let path = Config::path()?;
+        let table = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents.parse()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => toml::Table::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, table })
+    }
+
+    pub fn show(&self) -> String {
+        self.table.to_string()
+    }
+
+    /// `key` is relative to the profile, e.g. `project` or `labels.team`
+    pub fn get(&self, profile: &str, key: &str) -> Option<&toml::Value> {
+        let path = Self::path(profile, key);
+        let mut value = self.table.get(path[0])?;
+        for part in &path[1..] {
+            value = value.get(part)?;
+        }
+        Some(value)
+    }
+
+    pub fn set(&mut self, profile: &str, key: &str, value: &str) -> anyhow::Result<()> {
+        // edits a copy, so a rejected value leaves the file as it was
+        let mut edited = self.table.clone();
+        let mut table = &mut edited;
+        let path = Self::path(profile, key);
+        let (last, parents) = path.split_last().expect("path is never empty");
+
+        for part in parents {
+            table = table
+                .entry(part.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| anyhow::anyhow!("`{}` is not a table", part))?;
+        }
+
+        table.insert(last.to_string(), Self::typed(&path, value)?);
+
+        // refuse to write anything that would not load again
+        Config::parse(&edited.to_string())
+            .map_err(|e| anyhow::anyhow!("invalid value for `{}`: {}", key, e.message()))?;
+        self.table = edited;
+        Ok(())
+    }
+
+    /// the value as the type of the setting, so `labels.year = "2024"` stays a string
+    fn typed(path: &[&str], value: &str) -> anyhow::Result<toml::Value> {
+        let setting = match path {
+            ["profiles", _, setting] => *setting,
+            _ => return Ok(toml::Value::String(value.to_string())),
+        };
+
+        Ok(match setting {
+            "max_bytes_billed" | "catalog_ttl" | "impersonation_lifetime" => value
+                .parse::<u64>()
+                .ok()
+                .and_then(|n| i64::try_from(n).ok())
+                .map(toml::Value::Integer)
+                .ok_or_else(|| anyhow::anyhow!("`{}` expects a number", setting))?,
+            "allow_write" => value
+                .parse::<bool>()
+                .map(toml::Value::Boolean)
+                .map_err(|_| anyhow::anyhow!("`{}` expects true or false", setting))?,
+            "scopes" => toml::Value::Array(
+                value
+                    .split(',')
+                    .map(|scope| toml::Value::String(scope.trim().to_string()))
+                    .collect(),
+            ),
+            _ => toml::Value::String(value.to_string()),
+        })
+    }
+
+    /// `default_profile` is the only setting outside of a profile
+    fn path<'a>(profile: &'a str, key: &'a str) -> Vec<&'a str> {
+        if key == "default_profile" {
+            return vec![key];
+        }
+
+        let mut path = vec!["profiles", profile];
+        path.extend(key.split('.'));
+        path
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, self.table.to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Config, CredentialSource, Editor};
+
+    #[test]
+    fn resolves_profiles() {
+        let config = Config::parse(
+            r#"
+            default_profile = "dev"
+
+            [profiles.dev]
+            project = "dev-project"
+            credentials = "application-default"
+            max_bytes_billed = 1000
+
+            [profiles.dev.labels]
+            team = "analytics"
+
+            [profiles.prod]
+            key = "/keys/prod.json"
+            "#,
+        )
+        .unwrap();
+
+        let dev = config.profile(None).unwrap();
+        assert_eq!(dev.project.as_deref(), Some("dev-project"));
+        assert_eq!(dev.credentials, Some(CredentialSource::ApplicationDefault));
+        assert_eq!(dev.max_bytes_billed, Some(1000));
+        assert_eq!(dev.labels["team"], "analytics");
+
+        let prod = config.profile(Some("prod")).unwrap();
+        assert!(prod.key.is_some());
+
+        assert!(config.profile(Some("missing")).is_err());
+    }
+
+    #[test]
+    fn edits_profiles() {
+        let mut editor = Editor {
+            path: "config.toml".into(),
+            table: toml::Table::new(),
+        };
+
+        editor.set("dev", "project", "dev-project").unwrap();
+        editor.set("dev", "max_bytes_billed", "1000").unwrap();
+        editor.set("dev", "labels.team", "analytics").unwrap();
+        editor.set("dev", "allow_write", "true").unwrap();
+        editor.set("dev", "default_profile", "dev").unwrap();
+        assert!(editor.set("dev", "credentials", "unknown").is_err());
+
+        assert_eq!(
+            editor.get("dev", "labels.team").and_then(|v| v.as_str()),
+            Some("analytics")
+        );
+
+        let config = Config::parse(&editor.show()).unwrap();
+        let dev = config.profile(None).unwrap();
+        assert_eq!(dev.project.as_deref(), Some("dev-project"));
+        assert_eq!(dev.max_bytes_billed, Some(1000));
+        assert_eq!(dev.allow_write, Some(true));
+    }
+
+    #[test]
+    fn types_values_by_setting() {
+        let mut editor = Editor {
+            path: "config.toml".into(),
+            table: toml::Table::new(),
+        };
+
+        editor.set("dev", "labels.year", "2024").unwrap();
+        editor.set("dev", "labels.billable", "true").unwrap();
+        editor.set("dev", "project", "1234").unwrap();
+        editor
+            .set("dev", "scopes", "https://a.example, https://b.example")
+            .unwrap();
+        assert!(editor.set("dev", "max_bytes_billed", "lots").is_err());
+        assert!(editor.set("dev", "allow_write", "yes").is_err());
+
+        let config = Config::parse(&editor.show()).unwrap();
+        let dev = config.profile(Some("dev")).unwrap();
+        assert_eq!(dev.labels["year"], "2024");
+        assert_eq!(dev.labels["billable"], "true");
+        assert_eq!(dev.project.as_deref(), Some("1234"));
+        assert_eq!(dev.scopes, ["https://a.example", "https://b.example"]);
+    }
+}
@@ -0,0 +1,26 @@
+//! <https://cloud.google.com/bigquery/docs/reference/rest/v2/datasets>
+
+/// <https://cloud.google.com/bigquery/docs/reference/rest/v2/datasets/list>
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetList {
+    /// missing when the project has no datasets
+    #[serde(default)]
+    pub datasets: Vec<DatasetListItem>,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetListItem {
+    pub id: String,
+    pub dataset_reference: DatasetReference,
+    pub location: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetReference {
+    pub project_id: String,
+    pub dataset_id: String,
+}
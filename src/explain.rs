@@ -0,0 +1,241 @@
+//! renders the execution plan of a query job as a tree of stages
+//!
+//! <https://cloud.google.com/bigquery/docs/query-plan-explanation>
+
+use crate::job::{bytes, parse, ExplainQueryStage, Job, QueryTimelineSample};
+use std::collections::{HashMap, HashSet};
+
+/// a stage is skewed when its slowest worker takes this many times longer than the average one
+const SKEW_THRESHOLD: f64 = 5.0;
+/// fast stages are never flagged as skewed
+const SKEW_MIN_MS: u64 = 1000;
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+pub fn render(job: &Job) -> String {
+    let query = job.statistics.as_ref().and_then(|s| s.query.as_ref());
+
+    let stages = query
+        .and_then(|q| q.query_plan.as_deref())
+        .unwrap_or_default();
+
+    if stages.is_empty() {
+        return "no query plan is available for this job".to_string();
+    }
+
+    let mut output = Vec::new();
+
+    if let Some(timeline) = query.and_then(|q| q.timeline.as_deref()) {
+        if let Some(line) = slot_usage(timeline) {
+            output.push(line);
+        }
+    }
+
+    let by_id: HashMap<&str, &ExplainQueryStage> = stages
+        .iter()
+        .filter_map(|s| Some((s.id.as_deref()?, s)))
+        .collect();
+
+    // the roots of the tree are the stages that are not read by any other stage
+    let consumed: HashSet<&str> = stages
+        .iter()
+        .flat_map(|s| s.input_stages.iter().map(String::as_str))
+        .collect();
+
+    let mut visited = HashSet::new();
+    for root in stages
+        .iter()
+        .rev()
+        .filter(|s| !s.id.as_deref().is_some_and(|id| consumed.contains(id)))
+    {
+        tree(root, &by_id, "", None, &mut visited, &mut output);
+    }
+
+    output.join("\n")
+}
+
+fn tree<'a>(
+    stage: &'a ExplainQueryStage,
+    by_id: &HashMap<&str, &'a ExplainQueryStage>,
+    prefix: &str,
+    last: Option<bool>,
+    visited: &mut HashSet<&'a str>,
+    output: &mut Vec<String>,
+) {
+    let (branch, indent) = match last {
+        None => ("", ""),
+        Some(true) => ("└─ ", "   "),
+        Some(false) => ("├─ ", "│  "),
+    };
+
+    let id = stage.id.as_deref().unwrap_or_default();
+    if !visited.insert(id) {
+        output.push(format!("{}{}{} (see above)", prefix, branch, stage.name));
+        return;
+    }
+
+    output.push(format!("{}{}{}", prefix, branch, summary(stage)));
+
+    let child_prefix = format!("{}{}", prefix, indent);
+    for detail in details(stage) {
+        output.push(format!("{}   {}", child_prefix, detail));
+    }
+
+    let inputs: Vec<&ExplainQueryStage> = stage
+        .input_stages
+        .iter()
+        .filter_map(|id| by_id.get(id.as_str()).copied())
+        .collect();
+
+    for (i, input) in inputs.iter().enumerate() {
+        let last = i == inputs.len() - 1;
+        tree(input, by_id, &child_prefix, Some(last), visited, output);
+    }
+}
+
+fn summary(stage: &ExplainQueryStage) -> String {
+    let mut line = format!(
+        "{} [{}] rows {} → {}",
+        stage.name,
+        stage.status.as_deref().unwrap_or("UNKNOWN"),
+        count(&stage.records_read),
+        count(&stage.records_written)
+    );
+
+    if let Some(shuffle) = parse(&stage.shuffle_output_bytes) {
+        line.push_str(&format!(", shuffle {}", bytes(shuffle)));
+    }
+
+    if let Some(slot_ms) = parse(&stage.slot_ms) {
+        line.push_str(&format!(", {} slot ms", slot_ms));
+    }
+
+    line
+}
+
+fn details(stage: &ExplainQueryStage) -> Vec<String> {
+    let ratio = |avg: Option<f64>, max: Option<f64>| {
+        format!("{:.2}/{:.2}", avg.unwrap_or(0.0), max.unwrap_or(0.0))
+    };
+
+    let mut details = vec![format!(
+        "wait {} read {} compute {} write {} (avg/max)",
+        ratio(stage.wait_ratio_avg, stage.wait_ratio_max),
+        ratio(stage.read_ratio_avg, stage.read_ratio_max),
+        ratio(stage.compute_ratio_avg, stage.compute_ratio_max),
+        ratio(stage.write_ratio_avg, stage.write_ratio_max),
+    )];
+
+    if let Some(skew) = skew(stage) {
+        details.push(format!(
+            "⚠ skewed: slowest worker computes {:.1}x longer than average",
+            skew
+        ));
+    }
+
+    if let Some(spilled) = parse(&stage.shuffle_output_bytes_spilled).filter(|s| *s > 0) {
+        details.push(format!("⚠ spilled {} of shuffle to disk", bytes(spilled)));
+    }
+
+    details
+}
+
+/// ratio of the slowest to the average compute time, when it is large enough to matter
+pub fn skew(stage: &ExplainQueryStage) -> Option<f64> {
+    let avg = parse(&stage.compute_ms_avg)?;
+    let max = parse(&stage.compute_ms_max)?;
+
+    if avg == 0 || max < SKEW_MIN_MS {
+        return None;
+    }
+
+    let ratio = max as f64 / avg as f64;
+    (ratio >= SKEW_THRESHOLD).then_some(ratio)
+}
+
+/// average number of slots in use between each sample, e.g. `slots ▁▃█▅▂ peak 120 avg 48`
+pub fn slot_usage(timeline: &[QueryTimelineSample]) -> Option<String> {
+    let mut previous = (0, 0);
+    let slots: Vec<f64> = timeline
+        .iter()
+        .filter_map(|sample| {
+            let elapsed = parse(&sample.elapsed_ms)?;
+            let slot_ms = parse(&sample.total_slot_ms)?;
+            let (previous_elapsed, previous_slot_ms) =
+                std::mem::replace(&mut previous, (elapsed, slot_ms));
+
+            let elapsed = elapsed.checked_sub(previous_elapsed).filter(|e| *e > 0)?;
+            Some(slot_ms.saturating_sub(previous_slot_ms) as f64 / elapsed as f64)
+        })
+        .collect();
+
+    let peak = slots.iter().cloned().fold(0.0, f64::max);
+    if slots.is_empty() || peak == 0.0 {
+        return None;
+    }
+
+    let sparkline: String = slots
+        .iter()
+        .map(|s| SPARKS[((s / peak) * (SPARKS.len() - 1) as f64).round() as usize])
+        .collect();
+    let average = slots.iter().sum::<f64>() / slots.len() as f64;
+
+    Some(format!(
+        "slots {} peak {:.0} avg {:.0}",
+        sparkline, peak, average
+    ))
+}
+
+/// formats large row counts compactly, e.g. `1.2M`
+fn count(value: &Option<String>) -> String {
+    let Some(count) = parse(value) else {
+        return "?".to_string();
+    };
+
+    match count {
+        c if c >= 1_000_000_000 => format!("{:.1}B", c as f64 / 1e9),
+        c if c >= 1_000_000 => format!("{:.1}M", c as f64 / 1e6),
+        c if c >= 1_000 => format!("{:.1}K", c as f64 / 1e3),
+        c => c.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::render;
+    use crate::job::Job;
+
+    #[test]
+    fn renders_stage_tree() {
+        let job: Job = serde_json::from_value(serde_json::json!({
+            "jobReference": { "projectId": "test", "jobId": "job_123", "location": "US" },
+            "statistics": { "query": {
+                "queryPlan": [
+                    { "name": "S00: Input", "id": "0", "status": "COMPLETE", "recordsRead": "1500000", "recordsWritten": "2000",
+                      "computeMsAvg": "100", "computeMsMax": "2000", "shuffleOutputBytes": "2048" },
+                    { "name": "S01: Input", "id": "1", "status": "COMPLETE", "recordsRead": "10", "recordsWritten": "10",
+                      "shuffleOutputBytesSpilled": "1048576" },
+                    { "name": "S02: Join+", "id": "2", "status": "COMPLETE", "inputStages": ["0", "1"], "recordsRead": "2010", "recordsWritten": "5" }
+                ],
+                "timeline": [
+                    { "elapsedMs": "1000", "totalSlotMs": "1000" },
+                    { "elapsedMs": "2000", "totalSlotMs": "5000" },
+                    { "elapsedMs": "3000", "totalSlotMs": "7000" }
+                ]
+            }}
+        }))
+        .unwrap();
+
+        let plan = render(&job);
+        let lines: Vec<&str> = plan.lines().collect();
+
+        assert_eq!(lines[0], "slots ▃█▅ peak 4 avg 2");
+        assert_eq!(lines[1], "S02: Join+ [COMPLETE] rows 2.0K → 5");
+        assert_eq!(
+            lines[3],
+            "├─ S00: Input [COMPLETE] rows 1.5M → 2.0K, shuffle 2.00 KiB"
+        );
+        assert!(lines[5].contains("⚠ skewed: slowest worker computes 20.0x longer than average"));
+        assert_eq!(lines[6], "└─ S01: Input [COMPLETE] rows 10 → 10");
+        assert!(lines[8].contains("⚠ spilled 1.00 MiB of shuffle to disk"));
+    }
+}
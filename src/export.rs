@@ -0,0 +1,178 @@
+//! writes query results into arrow based file formats, one page at a time
+//!
+//! only available with the `arrow` feature enabled, parquet additionally requires the `parquet` feature
+
+use crate::{arrow::RecordBatches, query::response::QueryResponse};
+use arrow::{datatypes::SchemaRef, error::ArrowError, ipc::writer::FileWriter};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, io::Write, sync::Arc};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    /// arrow IPC file format
+    Arrow,
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// job metadata that is embedded into the file
+///
+/// keys are prefixed with `bigquery.`
+pub fn job_metadata(response: &QueryResponse, query: &str) -> HashMap<String, String> {
+    let job = &response.job_reference;
+    let mut metadata = HashMap::new();
+
+    metadata.insert("bigquery.project_id".to_string(), job.project_id.clone());
+    metadata.insert("bigquery.location".to_string(), job.location.clone());
+    if let Some(job_id) = &job.job_id {
+        metadata.insert("bigquery.job_id".to_string(), job_id.clone());
+    }
+    metadata.insert("bigquery.query_sha256".to_string(), query_hash(query));
+
+    metadata
+}
+
+/// hex encoded sha256 of the query text
+pub fn query_hash(query: &str) -> String {
+    Sha256::digest(query.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// writes every batch into `writer` as soon as its page has been fetched
+///
+/// returns the number of rows written
+pub fn write<W: Write + Send>(
+    writer: W,
+    format: FileFormat,
+    batches: RecordBatches,
+    metadata: HashMap<String, String>,
+) -> Result<usize, ArrowError> {
+    let Some(schema) = batches.schema() else {
+        return Err(ArrowError::SchemaError(
+            "query did not return a result set".to_string(),
+        ));
+    };
+
+    let schema: SchemaRef = Arc::new(schema.as_ref().clone().with_metadata(metadata));
+    let mut rows = 0;
+
+    match format {
+        FileFormat::Arrow => {
+            let mut writer = FileWriter::try_new(writer, &schema)?;
+            for batch in batches {
+                let batch = batch?.with_schema(schema.clone())?;
+                rows += batch.num_rows();
+                writer.write(&batch)?;
+            }
+            writer.finish()?;
+        }
+        #[cfg(feature = "parquet")]
+        FileFormat::Parquet => {
+            use parquet::{arrow::ArrowWriter, format::KeyValue};
+
+            let mut writer = ArrowWriter::try_new(writer, schema.clone(), None)
+                .map_err(|e| ArrowError::ExternalError(Box::new(e)))?;
+
+            for (key, value) in schema.metadata() {
+                writer.append_key_value_metadata(KeyValue::new(key.clone(), value.clone()));
+            }
+
+            for batch in batches {
+                let batch = batch?.with_schema(schema.clone())?;
+                rows += batch.num_rows();
+                writer
+                    .write(&batch)
+                    .map_err(|e| ArrowError::ExternalError(Box::new(e)))?;
+                // flush each page into its own row group so memory stays bounded
+                writer
+                    .flush()
+                    .map_err(|e| ArrowError::ExternalError(Box::new(e)))?;
+            }
+
+            writer
+                .close()
+                .map_err(|e| ArrowError::ExternalError(Box::new(e)))?;
+        }
+    }
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{job_metadata, write, FileFormat};
+    use crate::{api::Client, query::response::QueryResponse};
+
+    fn response() -> QueryResponse {
+        serde_json::from_value(serde_json::json!({
+            "kind": "bigquery#queryResponse",
+            "jobReference": { "projectId": "test", "jobId": "job_123", "location": "US" },
+            "jobComplete": true,
+            "schema": { "fields": [
+                { "name": "name", "type": "STRING", "mode": "NULLABLE", "description": "the name" },
+                { "name": "scores", "type": "INTEGER", "mode": "REPEATED" }
+            ]},
+            "rows": [
+                { "f": [{ "v": "a" }, { "v": [{ "v": "1" }, { "v": "2" }] }] },
+                { "f": [{ "v": null }, { "v": [] }] }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn writes_arrow_with_metadata() {
+        let client = Client::bq_client(String::new(), "test");
+        let response = response();
+        let metadata = job_metadata(&response, "select 1");
+
+        let mut buffer = Vec::new();
+        let rows = write(
+            &mut buffer,
+            FileFormat::Arrow,
+            response.into_record_batches(&client),
+            metadata,
+        )
+        .unwrap();
+        assert_eq!(rows, 2);
+
+        let reader =
+            arrow::ipc::reader::FileReader::try_new(std::io::Cursor::new(buffer), None).unwrap();
+        let schema = reader.schema();
+        assert_eq!(schema.metadata()["bigquery.job_id"], "job_123");
+        assert_eq!(schema.field(0).metadata()["description"], "the name");
+        assert_eq!(reader.map(|b| b.unwrap().num_rows()).sum::<usize>(), 2);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn writes_parquet_with_metadata() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let client = Client::bq_client(String::new(), "test");
+        let response = response();
+        let metadata = job_metadata(&response, "select 1");
+
+        let path = std::env::temp_dir().join("bq-rs-writes-parquet-with-metadata.parquet");
+        write(
+            std::fs::File::create(&path).unwrap(),
+            FileFormat::Parquet,
+            response.into_record_batches(&client),
+            metadata,
+        )
+        .unwrap();
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let file = reader.metadata().file_metadata();
+        assert_eq!(file.num_rows(), 2);
+        assert!(file
+            .key_value_metadata()
+            .unwrap()
+            .iter()
+            .any(|kv| kv.key == "bigquery.query_sha256"));
+
+        std::fs::remove_file(&path).ok();
+    }
+}
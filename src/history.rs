@@ -0,0 +1,327 @@
+//! every `bq-rs query` is appended to a local log, so the query and job behind a result can be found again
+
+use crate::config::Config;
+use bq_rs::query::request::QueryParameter;
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Read, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Entry {
+    /// counted from 1 in the order the queries ran
+    pub id: usize,
+    /// seconds since the epoch
+    pub timestamp: i64,
+    pub profile: String,
+    pub project_id: String,
+    pub query: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<QueryParameter>,
+    pub job_id: Option<String>,
+    pub location: Option<String>,
+    pub bytes_billed: Option<u64>,
+    pub duration_ms: u64,
+    pub status: Status,
+    pub error: Option<String>,
+}
+
+impl Entry {
+    pub fn new(
+        profile: String,
+        project_id: String,
+        query: String,
+        parameters: Vec<QueryParameter>,
+    ) -> Self {
+        Self {
+            id: 0,
+            timestamp: chrono::Utc::now().timestamp(),
+            profile,
+            project_id,
+            query,
+            parameters,
+            job_id: None,
+            location: None,
+            bytes_billed: None,
+            duration_ms: 0,
+            status: Status::Succeeded,
+            error: None,
+        }
+    }
+
+    /// runs the query with `f`, which fills in the job, and appends the outcome to the history
+    pub fn record(mut self, f: impl FnOnce(&mut Self) -> anyhow::Result<()>) -> anyhow::Result<()> {
+        let started = Instant::now();
+        let result = f(&mut self);
+
+        self.duration_ms = started.elapsed().as_millis() as u64;
+        if let Err(e) = &result {
+            self.error = Some(e.to_string());
+            self.status = Status::Failed;
+        }
+
+        // a query that ran should not fail because it could not be recorded
+        if let Err(e) = History::open().and_then(|history| history.append(&mut self)) {
+            log::warn!("failed to record the query in the history: {}", e);
+        }
+
+        result
+    }
+
+    fn line(&self) -> String {
+        let timestamp = chrono::DateTime::from_timestamp(self.timestamp, 0)
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let status = match self.status {
+            Status::Succeeded => "ok",
+            Status::Failed => "failed",
+        };
+        let query: String = self.query.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        format!(
+            "{}\t{}\t{}\t{}\t{}",
+            self.id, timestamp, self.project_id, status, query
+        )
+    }
+}
+
+impl std::fmt::Display for Entry {
+    /// one line per entry, the alternate form lists every field followed by the query
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !f.alternate() {
+            return write!(f, "{}", self.line());
+        }
+
+        let na = || "N/A".to_string();
+        writeln!(f, "id:\t\t{}", self.id)?;
+        writeln!(
+            f,
+            "time:\t\t{}",
+            chrono::DateTime::from_timestamp(self.timestamp, 0)
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_else(na)
+        )?;
+        writeln!(f, "profile:\t{}", self.profile)?;
+        writeln!(f, "project:\t{}", self.project_id)?;
+        writeln!(
+            f,
+            "job:\t\t{} ({})",
+            self.job_id.as_deref().unwrap_or("N/A"),
+            self.location.as_deref().unwrap_or("N/A")
+        )?;
+        writeln!(
+            f,
+            "billed:\t\t{}",
+            self.bytes_billed.map(bq_rs::job::bytes).unwrap_or_else(na)
+        )?;
+        writeln!(f, "duration:\t{:.3}s", self.duration_ms as f64 / 1000.0)?;
+        match &self.error {
+            Some(error) => writeln!(f, "status:\t\tfailed\nerror:\t\t{}", error)?,
+            None => writeln!(f, "status:\t\tsucceeded")?,
+        }
+        if !self.parameters.is_empty() {
+            writeln!(
+                f,
+                "parameters:\t{}",
+                serde_json::to_string(&self.parameters).map_err(|_| std::fmt::Error)?
+            )?;
+        }
+        write!(f, "\n{}", self.query)
+    }
+}
+
+/// which entries `history ls` and `history search` list
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub project_id: Option<String>,
+    pub profile: Option<String>,
+    pub status: Option<Status>,
+    /// seconds since the epoch
+    pub since: Option<i64>,
+    /// case-insensitive, matched against the query and job id
+    pub pattern: Option<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let pattern = self.pattern.as_ref().map(|p| p.to_lowercase());
+
+        self.project_id
+            .as_ref()
+            .is_none_or(|p| *p == entry.project_id)
+            && self.profile.as_ref().is_none_or(|p| *p == entry.profile)
+            && self.status.is_none_or(|s| s == entry.status)
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && pattern.is_none_or(|p| {
+                entry.query.to_lowercase().contains(&p)
+                    || entry
+                        .job_id
+                        .as_ref()
+                        .is_some_and(|id| id.to_lowercase().contains(&p))
+            })
+    }
+}
+
+/// one json entry per line, lines are only ever appended
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn open() -> anyhow::Result<Self> {
+        Ok(Self {
+            path: Config::data_dir()?.join("history.jsonl"),
+        })
+    }
+
+    pub fn entries(&self) -> anyhow::Result<Vec<Entry>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(Self::parse(&contents)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// a line cut short by an interrupted write is skipped rather than losing the whole history
+    fn parse(contents: &str) -> Vec<Entry> {
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    log::warn!("skipping a history entry: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// assigns the entry the next id
+    ///
+    /// the file stays locked from reading the last id to writing the entry, so queries running
+    /// at the same time never share an id
+    pub fn append(&self, entry: &mut Entry) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        file.lock_exclusive()?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        entry.id = Self::parse(&contents).last().map_or(0, |last| last.id) + 1;
+
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        // closing the file releases the lock
+        Ok(())
+    }
+
+    /// looks an entry up by its id or job id
+    pub fn get(&self, entry: &str) -> anyhow::Result<Entry> {
+        let id = entry.parse::<usize>().ok();
+
+        self.entries()?
+            .into_iter()
+            .find(|e| Some(e.id) == id || e.job_id.as_deref() == Some(entry))
+            .ok_or_else(|| anyhow::anyhow!("no history entry `{}`", entry))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Entry, Filter, History, Status};
+
+    #[test]
+    fn appends_and_filters_entries() {
+        let path = std::env::temp_dir().join("bq-rs-appends-and-filters-entries.jsonl");
+        let _ = std::fs::remove_file(&path);
+        let history = History { path: path.clone() };
+
+        let mut first = Entry::new(
+            "default".to_string(),
+            "test".to_string(),
+            "SELECT 1".to_string(),
+            Vec::new(),
+        );
+        first.job_id = Some("job_abc".to_string());
+        history.append(&mut first).unwrap();
+
+        let mut second = Entry::new(
+            "prod".to_string(),
+            "other".to_string(),
+            "SELECT * FROM sales.orders".to_string(),
+            Vec::new(),
+        );
+        second.status = Status::Failed;
+        history.append(&mut second).unwrap();
+
+        let entries = history.entries().unwrap();
+        assert_eq!(entries.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(history.get("job_abc").unwrap().id, 1);
+        assert_eq!(history.get("2").unwrap().project_id, "other");
+        assert!(history.get("3").is_err());
+
+        let filter = Filter {
+            status: Some(Status::Failed),
+            pattern: Some("ORDERS".to_string()),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&entries[0]));
+        assert!(filter.matches(&entries[1]));
+
+        let filter = Filter {
+            profile: Some("prod".to_string()),
+            project_id: Some("test".to_string()),
+            ..Default::default()
+        };
+        assert!(entries.iter().all(|e| !filter.matches(e)));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn concurrent_appends_get_distinct_ids() {
+        let path = std::env::temp_dir().join("bq-rs-concurrent-appends-get-distinct-ids.jsonl");
+        let _ = std::fs::remove_file(&path);
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let history = History { path: path.clone() };
+                std::thread::spawn(move || {
+                    let mut entry = Entry::new(
+                        "default".to_string(),
+                        "test".to_string(),
+                        "SELECT 1".to_string(),
+                        Vec::new(),
+                    );
+                    history.append(&mut entry).unwrap();
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let history = History { path: path.clone() };
+        let mut ids: Vec<usize> = history.entries().unwrap().iter().map(|e| e.id).collect();
+        ids.sort();
+        assert_eq!(ids, (1..=8).collect::<Vec<_>>());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,357 @@
+//! <https://cloud.google.com/bigquery/docs/reference/rest/v2/Job>
+
+use crate::{
+    query::response::{ErrorProto, JobReference, QueryResponse},
+    table::TableReference,
+};
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub id: Option<String>,
+    pub job_reference: JobReference,
+    pub statistics: Option<JobStatistics>,
+    pub status: Option<JobStatus>,
+    pub user_email: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatus {
+    pub state: String,
+    pub error_result: Option<ErrorProto>,
+    pub errors: Option<Vec<ErrorProto>>,
+}
+
+/// <https://cloud.google.com/bigquery/docs/reference/rest/v2/Job#jobstatistics>
+///
+/// int64 values are encoded as strings by the REST API
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatistics {
+    /// milliseconds since the epoch
+    pub creation_time: Option<String>,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub total_bytes_processed: Option<String>,
+    pub total_slot_ms: Option<String>,
+    pub query: Option<QueryStatistics>,
+    /// set on the child jobs of a script
+    pub parent_job_id: Option<String>,
+    pub num_child_jobs: Option<String>,
+    pub script_statistics: Option<ScriptStatistics>,
+}
+
+/// <https://cloud.google.com/bigquery/docs/reference/rest/v2/Job#scriptstatistics>
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptStatistics {
+    /// `STATEMENT` or `EXPRESSION`, expressions are conditions and default values
+    pub evaluation_kind: Option<String>,
+    /// innermost frame first
+    #[serde(default)]
+    pub stack_frames: Vec<ScriptStackFrame>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptStackFrame {
+    pub start_line: Option<u32>,
+    pub start_column: Option<u32>,
+    pub end_line: Option<u32>,
+    pub end_column: Option<u32>,
+    pub procedure_id: Option<String>,
+    pub text: Option<String>,
+}
+
+/// <https://cloud.google.com/bigquery/docs/reference/rest/v2/jobs/list>
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobList {
+    #[serde(default)]
+    pub jobs: Vec<Job>,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryStatistics {
+    pub total_bytes_processed: Option<String>,
+    pub total_bytes_billed: Option<String>,
+    pub billing_tier: Option<i32>,
+    pub cache_hit: Option<bool>,
+    pub statement_type: Option<String>,
+    pub num_dml_affected_rows: Option<String>,
+    pub dml_stats: Option<DmlStats>,
+    pub query_plan: Option<Vec<ExplainQueryStage>>,
+    pub timeline: Option<Vec<QueryTimelineSample>>,
+    /// tables and views the query read from
+    #[serde(default)]
+    pub referenced_tables: Vec<TableReference>,
+}
+
+/// <https://cloud.google.com/bigquery/docs/reference/rest/v2/Job#explainquerystage>
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExplainQueryStage {
+    pub name: String,
+    pub id: Option<String>,
+    pub status: Option<String>,
+    #[serde(default)]
+    pub input_stages: Vec<String>,
+    pub start_ms: Option<String>,
+    pub end_ms: Option<String>,
+    pub wait_ratio_avg: Option<f64>,
+    pub wait_ratio_max: Option<f64>,
+    pub read_ratio_avg: Option<f64>,
+    pub read_ratio_max: Option<f64>,
+    pub compute_ratio_avg: Option<f64>,
+    pub compute_ratio_max: Option<f64>,
+    pub write_ratio_avg: Option<f64>,
+    pub write_ratio_max: Option<f64>,
+    pub compute_ms_avg: Option<String>,
+    pub compute_ms_max: Option<String>,
+    pub shuffle_output_bytes: Option<String>,
+    pub shuffle_output_bytes_spilled: Option<String>,
+    pub records_read: Option<String>,
+    pub records_written: Option<String>,
+    pub parallel_inputs: Option<String>,
+    pub completed_parallel_inputs: Option<String>,
+    pub slot_ms: Option<String>,
+    #[serde(default)]
+    pub steps: Vec<ExplainQueryStep>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExplainQueryStep {
+    pub kind: String,
+    #[serde(default)]
+    pub substeps: Vec<String>,
+}
+
+/// <https://cloud.google.com/bigquery/docs/reference/rest/v2/Job#querytimelinesample>
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryTimelineSample {
+    pub elapsed_ms: Option<String>,
+    pub total_slot_ms: Option<String>,
+    pub pending_units: Option<String>,
+    pub completed_units: Option<String>,
+    pub active_units: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DmlStats {
+    pub inserted_row_count: Option<String>,
+    pub deleted_row_count: Option<String>,
+    pub updated_row_count: Option<String>,
+}
+
+impl Job {
+    /// link to the job in the google cloud console
+    pub fn console_url(&self) -> Option<String> {
+        let job = &self.job_reference;
+        Some(format!(
+            "https://console.cloud.google.com/bigquery?project={}&j=bq:{}:{}&page=queryresults",
+            job.project_id,
+            job.location,
+            job.job_id.as_deref()?
+        ))
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.status.as_ref().is_some_and(|s| s.state == "DONE")
+    }
+
+    /// the error the job failed with, if it did
+    pub fn error(&self) -> Option<&ErrorProto> {
+        self.status.as_ref()?.error_result.as_ref()
+    }
+
+    /// how long the job ran for, from start to end
+    pub fn duration(&self) -> Option<std::time::Duration> {
+        let statistics = self.statistics.as_ref()?;
+        let start = parse(&statistics.start_time)?;
+        let end = parse(&statistics.end_time)?;
+        Some(std::time::Duration::from_millis(end.checked_sub(start)?))
+    }
+}
+
+pub(crate) fn parse(value: &Option<String>) -> Option<u64> {
+    value.as_deref()?.parse().ok()
+}
+
+/// summary of a finished query, meant to be printed on stderr
+#[derive(Debug, Default, serde::Serialize)]
+pub struct Summary {
+    pub job_id: Option<String>,
+    pub location: String,
+    pub duration_ms: Option<u64>,
+    pub bytes_processed: Option<u64>,
+    pub bytes_billed: Option<u64>,
+    pub slot_ms: Option<u64>,
+    pub cache_hit: bool,
+    pub rows: Option<u64>,
+    pub dml_inserted: Option<u64>,
+    pub dml_updated: Option<u64>,
+    pub dml_deleted: Option<u64>,
+    pub console_url: Option<String>,
+}
+
+impl Summary {
+    pub fn new(response: &QueryResponse, job: Option<&Job>) -> Self {
+        let statistics = job.and_then(|j| j.statistics.as_ref());
+        let query = statistics.and_then(|s| s.query.as_ref());
+        let dml = query.and_then(|q| q.dml_stats.as_ref());
+
+        Self {
+            job_id: response.job_reference.job_id.clone(),
+            location: response.job_reference.location.clone(),
+            duration_ms: job.and_then(|j| j.duration()).map(|d| d.as_millis() as u64),
+            bytes_processed: statistics
+                .and_then(|s| parse(&s.total_bytes_processed))
+                .or(parse(&response.total_bytes_processed)),
+            bytes_billed: query.and_then(|q| parse(&q.total_bytes_billed)),
+            slot_ms: statistics.and_then(|s| parse(&s.total_slot_ms)),
+            cache_hit: query
+                .and_then(|q| q.cache_hit)
+                .unwrap_or(response.cache_hit),
+            rows: parse(&response.total_rows),
+            dml_inserted: dml.and_then(|d| parse(&d.inserted_row_count)),
+            dml_updated: dml.and_then(|d| parse(&d.updated_row_count)),
+            dml_deleted: dml.and_then(|d| parse(&d.deleted_row_count)),
+            console_url: job.and_then(|j| j.console_url()),
+        }
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let na = || "N/A".to_string();
+
+        writeln!(
+            f,
+            "job:\t\t{} ({})",
+            self.job_id.as_deref().unwrap_or("N/A"),
+            self.location
+        )?;
+        writeln!(
+            f,
+            "duration:\t{}",
+            self.duration_ms
+                .map(|ms| format!("{:.3}s", ms as f64 / 1000.0))
+                .unwrap_or_else(na)
+        )?;
+        writeln!(
+            f,
+            "processed:\t{}",
+            self.bytes_processed.map(bytes).unwrap_or_else(na)
+        )?;
+        writeln!(
+            f,
+            "billed:\t\t{}",
+            self.bytes_billed.map(bytes).unwrap_or_else(na)
+        )?;
+        writeln!(
+            f,
+            "slot ms:\t{}",
+            self.slot_ms.map(|s| s.to_string()).unwrap_or_else(na)
+        )?;
+        writeln!(f, "cache hit:\t{}", self.cache_hit)?;
+        writeln!(
+            f,
+            "rows:\t\t{}",
+            self.rows.map(|r| r.to_string()).unwrap_or_else(na)
+        )?;
+
+        if self.dml_inserted.is_some() || self.dml_updated.is_some() || self.dml_deleted.is_some() {
+            writeln!(
+                f,
+                "dml:\t\t{} inserted, {} updated, {} deleted",
+                self.dml_inserted.unwrap_or(0),
+                self.dml_updated.unwrap_or(0),
+                self.dml_deleted.unwrap_or(0)
+            )?;
+        }
+
+        write!(
+            f,
+            "console:\t{}",
+            self.console_url.as_deref().unwrap_or("N/A")
+        )
+    }
+}
+
+/// formats a byte count using binary units, e.g. `1.50 GiB`
+pub fn bytes(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bytes, Job, Summary};
+    use crate::query::response::QueryResponse;
+
+    #[test]
+    fn summarizes_job_statistics() {
+        let response: QueryResponse = serde_json::from_value(serde_json::json!({
+            "kind": "bigquery#queryResponse",
+            "jobReference": { "projectId": "test", "jobId": "job_123", "location": "US" },
+            "jobComplete": true,
+            "totalRows": "3",
+            "cacheHit": false
+        }))
+        .unwrap();
+
+        let job: Job = serde_json::from_value(serde_json::json!({
+            "jobReference": { "projectId": "test", "jobId": "job_123", "location": "US" },
+            "statistics": {
+                "creationTime": "1700000000000",
+                "startTime": "1700000000100",
+                "endTime": "1700000001600",
+                "totalBytesProcessed": "1048576",
+                "totalSlotMs": "250",
+                "query": {
+                    "totalBytesBilled": "10485760",
+                    "cacheHit": false,
+                    "dmlStats": { "insertedRowCount": "3" }
+                }
+            }
+        }))
+        .unwrap();
+
+        let summary = Summary::new(&response, Some(&job));
+        assert_eq!(summary.duration_ms, Some(1500));
+        assert_eq!(summary.bytes_billed, Some(10_485_760));
+        assert_eq!(summary.dml_inserted, Some(3));
+        assert_eq!(
+            summary.console_url.as_deref(),
+            Some("https://console.cloud.google.com/bigquery?project=test&j=bq:US:job_123&page=queryresults")
+        );
+
+        let text = summary.to_string();
+        assert!(text.contains("billed:\t\t10.00 MiB"));
+        assert!(text.contains("dml:\t\t3 inserted, 0 updated, 0 deleted"));
+    }
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(bytes(512), "512 B");
+        assert_eq!(bytes(1536), "1.50 KiB");
+    }
+}
@@ -1,2 +1,18 @@
 pub mod api;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod cache;
+pub mod catalog;
+pub mod dataset;
+pub mod explain;
+#[cfg(feature = "arrow")]
+pub mod export;
+pub mod job;
+pub mod policy;
 pub mod query;
+pub mod render;
+pub mod script;
+pub mod session;
+pub mod table;
+pub mod value;
+mod wkt;
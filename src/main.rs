@@ -1,9 +1,16 @@
 mod cli;
+mod config;
+mod history;
+mod sessions;
+mod shell;
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use cli::Cli;
 
 fn main() -> anyhow::Result<()> {
+    // answers completion requests from the shell, e.g. `source <(COMPLETE=bash bq-rs)`
+    clap_complete::CompleteEnv::with_factory(Cli::command).complete();
+
     env_logger::init();
     let cli = Cli::parse();
     cli.run()
@@ -0,0 +1,108 @@
+//! safe mode, what a query would do is found with a dry run before it runs
+//!
+//! <https://cloud.google.com/bigquery/docs/reference/rest/v2/Job#JobStatistics2.FIELDS.statement_type>
+
+use crate::{
+    api::{Client, Error},
+    query::request::QueryRequestBuilder,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    /// changes rows or schemas
+    Write,
+    /// drops or empties something, which cannot be undone after the time travel window
+    Destructive,
+    /// scripts and procedure calls, their statements are only known once they run
+    Unknown,
+}
+
+/// only statement types known to change nothing are reads, any other type may write
+pub fn access(statement_type: &str) -> Access {
+    match statement_type {
+        "SELECT" | "ASSERT" => Access::Read,
+        // transactions only commit statements that were checked on their own
+        "BEGIN_TRANSACTION" | "COMMIT_TRANSACTION" | "ROLLBACK_TRANSACTION" => Access::Read,
+        "TRUNCATE_TABLE" => Access::Destructive,
+        s if s.starts_with("DROP_") => Access::Destructive,
+        "SCRIPT" | "CALL" => Access::Unknown,
+        _ => Access::Write,
+    }
+}
+
+/// statements a script may consist of and still be a read, every other statement could write
+const SCRIPT_READS: [&str; 5] = ["SELECT", "WITH", "DECLARE", "SET", "ASSERT"];
+
+/// a script is a read when each of its statements starts with `SELECT`, `WITH`, `DECLARE`, `SET` or `ASSERT`
+///
+/// a `DROP` or `TRUNCATE` statement makes it destructive, anything else, including `CALL`,
+/// `EXECUTE IMMEDIATE` and blocks, stays unknown
+pub fn script_access(query: &str) -> Access {
+    let keywords = crate::script::keywords(query);
+    if keywords.iter().any(|k| k == "DROP" || k == "TRUNCATE") {
+        Access::Destructive
+    } else if keywords.iter().all(|k| SCRIPT_READS.contains(&k.as_str())) {
+        Access::Read
+    } else {
+        Access::Unknown
+    }
+}
+
+/// the statement type of the query, dry runs are free and run nothing
+pub fn statement_type(
+    client: &Client,
+    request: QueryRequestBuilder,
+) -> Result<Option<String>, Error> {
+    let job = client.jobs_insert(&request.dry_run().build())?;
+    Ok(job
+        .statistics
+        .and_then(|s| s.query)
+        .and_then(|q| q.statement_type))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{access, script_access, Access};
+
+    #[test]
+    fn classifies_statements() {
+        assert_eq!(access("SELECT"), Access::Read);
+        assert_eq!(access("ASSERT"), Access::Read);
+        assert_eq!(access("MERGE"), Access::Write);
+        assert_eq!(access("CREATE_TABLE_AS_SELECT"), Access::Write);
+        assert_eq!(access("ALTER_TABLE"), Access::Write);
+        assert_eq!(access("DROP_SCHEMA"), Access::Destructive);
+        assert_eq!(access("TRUNCATE_TABLE"), Access::Destructive);
+        assert_eq!(access("SCRIPT"), Access::Unknown);
+        assert_eq!(access("EXPORT_DATA"), Access::Write);
+        assert_eq!(access("LOAD_DATA"), Access::Write);
+        assert_eq!(access("GRANT"), Access::Write);
+        assert_eq!(access("A_FUTURE_STATEMENT"), Access::Write);
+    }
+
+    #[test]
+    fn classifies_scripts() {
+        assert_eq!(
+            script_access("DECLARE n INT64 DEFAULT 3; SELECT n; SELECT n * 2;"),
+            Access::Read
+        );
+        assert_eq!(
+            script_access("SET x = (SELECT 1);\nWITH t AS (SELECT x) SELECT * FROM t"),
+            Access::Read
+        );
+        assert_eq!(
+            script_access("DECLARE n INT64; DELETE FROM t WHERE true"),
+            Access::Unknown
+        );
+        assert_eq!(script_access("SELECT 1; CALL p()"), Access::Unknown);
+        assert_eq!(
+            script_access("SELECT 1; EXECUTE IMMEDIATE 'DROP TABLE t'"),
+            Access::Unknown
+        );
+        assert_eq!(
+            script_access("SELECT 1; BEGIN SELECT 2; END"),
+            Access::Unknown
+        );
+    }
+}
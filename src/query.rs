@@ -1,5 +1,5 @@
 pub mod request {
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct QueryRequestBuilder {
         query_request: QueryRequest,
     }
@@ -36,6 +36,54 @@ pub mod request {
             self
         }
 
+        /// runs the query inside an existing session, see `create_session`
+        pub fn session_id(mut self, session_id: String) -> Self {
+            self.query_request.connection_properties = Some(vec![ConnectionProperty {
+                key: "session_id".to_string(),
+                value: session_id,
+            }]);
+            self
+        }
+
+        /// timestamps are returned as integer microseconds instead of float seconds
+        pub fn use_int64_timestamp(mut self) -> Self {
+            self.query_request.format_options = Some(DataFormatOptions {
+                use_int64_timestamp: Some(true),
+            });
+            self
+        }
+
+        /// location of the datasets referenced by the query, e.g. `US` or `europe-west2`
+        pub fn location(mut self, location: String) -> Self {
+            self.query_request.location = Some(location);
+            self
+        }
+
+        pub fn labels(mut self, labels: std::collections::HashMap<String, String>) -> Self {
+            self.query_request.labels = Some(labels);
+            self
+        }
+
+        /// the query fails without incurring a charge when it would bill more bytes than this
+        pub fn maximum_bytes_billed(mut self, bytes: u64) -> Self {
+            self.query_request.maximum_bytes_billed = Some(bytes.to_string());
+            self
+        }
+
+        /// named parameters, referred to as `@name` in the query
+        pub fn query_parameters(mut self, parameters: Vec<QueryParameter>) -> Self {
+            self.query_request.parameter_mode = Some("NAMED".to_string());
+            self.query_request.query_parameters = Some(parameters);
+            self
+        }
+
+        pub fn parameters(&self) -> &[QueryParameter] {
+            self.query_request
+                .query_parameters
+                .as_deref()
+                .unwrap_or_default()
+        }
+
         pub fn build(self) -> QueryRequest {
             self.query_request
         }
@@ -47,7 +95,7 @@ pub mod request {
         }
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     /// <https://cloud.google.com/bigquery/docs/reference/rest/v2/jobs/query#queryrequest>
     pub struct QueryRequest {
         /// deprecated
@@ -82,7 +130,8 @@ pub mod request {
         pub fn new(query: String) -> Self {
             Self {
                 kind: None,
-                query: query.replace('\n', ""),
+                // line breaks are kept, `--` comments would otherwise swallow the rest of the query
+                query,
                 max_results: None,
                 default_dataset: None,
                 timeout_ms: None,
@@ -103,20 +152,62 @@ pub mod request {
         }
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    impl QueryRequest {
+        pub fn query(&self) -> &str {
+            &self.query
+        }
+
+        /// the equivalent `jobs.insert` body, which unlike `jobs.query` returns the job even when it fails
+        pub fn to_job(&self) -> serde_json::Value {
+            let mut job = serde_json::json!({
+                "configuration": {
+                    "query": {
+                        "query": self.query,
+                        "useLegacySql": self.use_legacy_sql,
+                        "useQueryCache": self.use_query_cache,
+                        "defaultDataset": self.default_dataset,
+                        "maximumBytesBilled": self.maximum_bytes_billed,
+                        "connectionProperties": self.connection_properties,
+                        "createSession": self.create_session,
+                        "parameterMode": self.parameter_mode,
+                        "queryParameters": self.query_parameters,
+                    },
+                    "dryRun": self.dry_run,
+                    "labels": self.labels,
+                }
+            });
+
+            if let Some(location) = &self.location {
+                job["jobReference"] = serde_json::json!({ "location": location });
+            }
+
+            job
+        }
+    }
+
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     pub struct DatasetReference {
         dataset_id: String,
         project_id: String,
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    impl DatasetReference {
+        pub fn new(project_id: String, dataset_id: String) -> Self {
+            Self {
+                dataset_id,
+                project_id,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     pub struct QueryParameter {
         name: String,
         parameter_type: QueryParameterType,
         parameter_value: QueryParameterValue,
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     pub struct QueryParameterType {
         #[serde(rename = "type")]
         type_: String,
@@ -124,26 +215,26 @@ pub mod request {
         struct_types: Option<Vec<StructType>>,
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     pub struct StructType {
         name: String,
         type_: QueryParameterType,
         description: String,
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     pub struct QueryParameterValue {
         value: Option<String>,
         array_values: Option<Vec<QueryParameterValue>>,
         struct_values: Option<std::collections::HashMap<String, QueryParameterValue>>,
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     pub struct DataFormatOptions {
         use_int64_timestamp: Option<bool>,
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     pub struct ConnectionProperty {
         key: String,
         value: String,
@@ -151,6 +242,7 @@ pub mod request {
 }
 
 pub mod response {
+    use crate::api::Error;
     use core::time;
 
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -218,13 +310,70 @@ pub mod response {
         #[serde(default)]
         pub cache_hit: bool,
         pub num_dml_affected_rows: Option<String>,
+        /// only present when the query created or ran in a session
+        pub session_info: Option<SessionInfo>,
+    }
+
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct SessionInfo {
+        pub session_id: String,
+    }
+
+    /// <https://docs.rs/csv/latest/csv/enum.QuoteStyle.html>
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum QuoteStyle {
+        /// only quote values that contain the delimiter, a quote or a line break
+        Necessary,
+        Always,
+        /// quote everything except numeric columns
+        NonNumeric,
+        /// never quote or escape values
+        Never,
     }
 
-    pub fn retry<T>(handler: impl Fn() -> Option<T>, retries: Option<u32>) -> T {
+    #[derive(Debug, Clone)]
+    pub struct CsvOptions {
+        pub delimiter: char,
+        pub header: bool,
+        /// written in place of `NULL` values
+        pub null: String,
+        pub quote: QuoteStyle,
+        pub line_terminator: String,
+        pub values: crate::value::FormatOptions,
+    }
+
+    impl Default for CsvOptions {
+        fn default() -> Self {
+            Self {
+                delimiter: ',',
+                header: true,
+                null: String::new(),
+                quote: QuoteStyle::Necessary,
+                line_terminator: "\n".to_string(),
+                values: Default::default(),
+            }
+        }
+    }
+
+    impl CsvOptions {
+        pub fn tsv() -> Self {
+            Self {
+                delimiter: '\t',
+                ..Self::default()
+            }
+        }
+    }
+
+    /// polls `handler` with an exponential backoff until it returns a result
+    pub fn retry<T>(
+        handler: impl Fn() -> Option<Result<T, Error>>,
+        retries: Option<u32>,
+    ) -> Result<T, Error> {
         let retries = retries.unwrap_or(0);
 
         if retries > 10 {
-            panic!("exceeded retry limit");
+            return Err(Error::new("exceeded retry limit"));
         }
 
         let base_delay = time::Duration::from_millis(400);
@@ -239,31 +388,38 @@ pub mod response {
     }
 
     impl QueryResponse {
-        pub fn retry(self, client: &crate::api::Client) -> Self {
+        pub fn retry(self, client: &crate::api::Client) -> Result<Self, Error> {
             if self.job_complete {
-                return self;
+                return Ok(self);
             }
 
             let Some(job_id) = &self.job_reference.job_id else {
-                panic!("no id found for incomplete job");
+                return Err(Error::new("no id found for incomplete job"));
             };
 
-            let handler = || {
-                let response = client.jobs_query_results(job_id, &self.job_reference.location);
-
-                if response.job_complete {
-                    Some(response)
-                } else {
-                    None
-                }
+            let handler = || match client.jobs_query_results(job_id, &self.job_reference.location) {
+                // query results do not repeat the session of the job
+                Ok(response) if response.job_complete => Some(Ok(QueryResponse {
+                    session_info: self.session_info.clone(),
+                    ..response
+                })),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
             };
 
             retry(handler, None)
         }
 
         /// follow proper csv convention: https://stackoverflow.com/a/769820
-        fn csv_formatting_rules(mut row: String) -> String {
-            let mut add_quotes = row.contains([',', '\n']);
+        fn csv_formatting_rules(mut row: String, options: &CsvOptions, numeric: bool) -> String {
+            let mut add_quotes = match options.quote {
+                QuoteStyle::Never => return row,
+                QuoteStyle::Always => true,
+                QuoteStyle::NonNumeric => !numeric,
+                QuoteStyle::Necessary => false,
+            };
+
+            add_quotes |= row.contains([options.delimiter, '\n', '\r']);
 
             if row.contains('"') {
                 row = row.replace('"', "\"\"");
@@ -279,16 +435,22 @@ pub mod response {
         }
 
         pub fn into_csv(self) -> String {
+            self.into_csv_with(&CsvOptions::default())
+        }
+
+        /// values are rendered according to their type, `REPEATED` and `RECORD` values are written as JSON text
+        pub fn into_csv_with(self, options: &CsvOptions) -> String {
             let mut rows: Vec<String> = Vec::new();
+            let delimiter = options.delimiter.to_string();
 
-            if let Some(schema) = self.schema {
-                let header: Vec<String> = schema
-                    .fields
-                    .into_iter()
-                    .map(|c| c.name)
-                    .map(Self::csv_formatting_rules)
+            let fields = self.schema.map(|s| s.fields).unwrap_or_default();
+
+            if options.header && !fields.is_empty() {
+                let header: Vec<String> = fields
+                    .iter()
+                    .map(|c| Self::csv_formatting_rules(c.name.clone(), options, false))
                     .collect();
-                rows.push(header.join(","));
+                rows.push(header.join(&delimiter));
             }
 
             let mut values: Vec<String> = self
@@ -298,19 +460,31 @@ pub mod response {
                     serde_json::Value::Array(a) => {
                         let row: Vec<String> = a
                             .into_iter()
-                            .map(|v| match v["v"].clone() {
-                                serde_json::Value::String(x) => x,
-                                serde_json::Value::Bool(x) => x.to_string(),
-                                serde_json::Value::Number(x) => x.to_string(),
-                                serde_json::Value::Null => String::new(),
-                                _ => String::new(),
-                                //serde_json::Value::Array(_) => todo!(),
-                                //serde_json::Value::Object(_) => todo!(),
+                            .enumerate()
+                            .map(|(i, v)| {
+                                let Some(field) = fields.get(i) else {
+                                    return Self::csv_formatting_rules(
+                                        v["v"].to_string(),
+                                        options,
+                                        false,
+                                    );
+                                };
+
+                                let value = crate::value::Value::decode(field, &v["v"]);
+                                // the null token is never quoted to keep it apart from strings
+                                if value.is_null() {
+                                    return options.null.clone();
+                                }
+
+                                // surround values with double quotes
+                                Self::csv_formatting_rules(
+                                    value.to_text(&options.values),
+                                    options,
+                                    field.is_numeric(),
+                                )
                             })
-                            // surround values with double quotes
-                            .map(Self::csv_formatting_rules)
                             .collect();
-                        Some(row.join(","))
+                        Some(row.join(&delimiter))
                     }
                     _ => None,
                 })
@@ -318,7 +492,10 @@ pub mod response {
 
             rows.append(values.as_mut());
 
-            rows.join("\n")
+            // every record ends with the terminator, the last one included
+            rows.iter()
+                .map(|row| format!("{}{}", row, options.line_terminator))
+                .collect()
         }
 
         #[allow(dead_code)]
@@ -356,18 +533,20 @@ pub mod response {
         }
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     #[serde(rename_all = "camelCase")]
     pub struct TableSchema {
         pub fields: Vec<TableFieldSchema>,
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     #[serde(rename_all = "camelCase")]
     pub struct TableFieldSchema {
         pub name: String,
         #[serde(rename = "type")]
         pub field_type: String,
+        /// the tables API omits the mode of nullable fields
+        #[serde(default = "TableFieldSchema::nullable")]
         pub mode: String,
         pub fields: Option<Vec<TableFieldSchema>>,
         pub description: Option<String>,
@@ -378,15 +557,42 @@ pub mod response {
         pub rounding_mode: Option<RoundingMode>,
         pub collation: Option<String>,
         pub default_value_expression: Option<String>,
+        /// only present when `field_type` is `RANGE`
+        pub range_element_type: Option<FieldElementType>,
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct FieldElementType {
+        #[serde(rename = "type")]
+        pub element_type: String,
+    }
+
+    impl TableFieldSchema {
+        fn nullable() -> String {
+            "NULLABLE".to_string()
+        }
+
+        pub fn is_repeated(&self) -> bool {
+            self.mode == "REPEATED"
+        }
+
+        pub fn is_numeric(&self) -> bool {
+            !self.is_repeated()
+                && matches!(
+                    self.field_type.as_str(),
+                    "INTEGER" | "INT64" | "FLOAT" | "FLOAT64" | "NUMERIC" | "BIGNUMERIC"
+                )
+        }
+    }
+
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     #[serde(rename_all = "camelCase")]
     pub struct PolicyTags {
         pub names: Vec<String>,
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     #[serde(rename_all = "camelCase")]
     #[allow(clippy::enum_variant_names)]
     pub enum RoundingMode {
@@ -395,7 +601,7 @@ pub mod response {
         RoundHalfEven,
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     #[serde(rename_all = "camelCase")]
     pub struct JobReference {
         pub project_id: String,
@@ -404,12 +610,66 @@ pub mod response {
         pub location: String,
     }
 
-    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
     #[serde(rename_all = "camelCase")]
     pub struct ErrorProto {
         pub reason: String,
+        /// not every error points to a location
+        #[serde(default)]
         pub location: String,
+        #[serde(default)]
         pub debug_info: String,
         pub message: String,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::response::{CsvOptions, QueryResponse, QuoteStyle};
+
+    fn response() -> QueryResponse {
+        serde_json::from_value(serde_json::json!({
+            "kind": "bigquery#queryResponse",
+            "jobReference": { "projectId": "test", "jobId": "job_123", "location": "US" },
+            "jobComplete": true,
+            "schema": { "fields": [
+                { "name": "name", "type": "STRING", "mode": "NULLABLE" },
+                { "name": "total", "type": "INTEGER", "mode": "NULLABLE" },
+                { "name": "tags", "type": "STRING", "mode": "REPEATED" },
+                { "name": "owner", "type": "RECORD", "mode": "NULLABLE", "fields": [
+                    { "name": "id", "type": "INTEGER", "mode": "NULLABLE" }
+                ]}
+            ]},
+            "rows": [
+                { "f": [{ "v": "a,b" }, { "v": "5" }, { "v": [{ "v": "x" }, { "v": "y" }] }, { "v": { "f": [{ "v": "1" }] } }] },
+                { "f": [{ "v": "" }, { "v": null }, { "v": [] }, { "v": null }] }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn csv_writes_nested_values_as_json() {
+        let csv = response().into_csv();
+        assert_eq!(
+            csv,
+            "name,total,tags,owner\n\"a,b\",5,\"[\"\"x\"\",\"\"y\"\"]\",\"{\"\"id\"\":1}\"\n,,[],\n"
+        );
+    }
+
+    #[test]
+    fn csv_dialect() {
+        let options = CsvOptions {
+            header: false,
+            null: "\\N".to_string(),
+            quote: QuoteStyle::NonNumeric,
+            line_terminator: "\r\n".to_string(),
+            ..CsvOptions::tsv()
+        };
+        let csv = response().into_csv_with(&options);
+        assert_eq!(
+            csv,
+            "\"a,b\"\t5\t\"[\"\"x\"\",\"\"y\"\"]\"\t\"{\"\"id\"\":1}\"\r\n\"\"\t\\N\t\"[]\"\t\\N\r\n"
+        );
+    }
+}
@@ -0,0 +1,471 @@
+//! human readable renderings of query results: terminal tables, markdown and html
+
+use crate::{
+    query::response::{QueryResponse, TableFieldSchema, TableSchema},
+    value::{FormatOptions, Value},
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// how `NULL` values are displayed by default, distinct from the empty string
+pub const NULL: &str = "NULL";
+
+pub struct TableOptions {
+    /// cells wider than this many terminal columns are truncated with `…`
+    pub max_width: usize,
+    pub values: FormatOptions,
+    /// text of `NULL` cells
+    pub null: String,
+    /// dims `NULL` cells with ANSI escapes, which sets them apart from the string `'NULL'` in a terminal
+    pub dim_nulls: bool,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        Self {
+            max_width: 40,
+            values: FormatOptions::default(),
+            null: NULL.to_string(),
+            dim_nulls: false,
+        }
+    }
+}
+
+/// a single rendered value
+struct Cell {
+    text: String,
+    null: bool,
+}
+
+/// the rendered header and rows of a query response
+struct Grid {
+    columns: Vec<Column>,
+    rows: Vec<Vec<Cell>>,
+}
+
+struct Column {
+    name: String,
+    numeric: bool,
+}
+
+impl Grid {
+    fn new(response: &QueryResponse, options: &FormatOptions, null: &str) -> Self {
+        let fields = response
+            .schema
+            .as_ref()
+            .map(|s| s.fields.as_slice())
+            .unwrap_or_default();
+
+        let columns = fields
+            .iter()
+            .map(|f| Column {
+                name: f.name.clone(),
+                numeric: f.is_numeric(),
+            })
+            .collect();
+
+        let rows = response
+            .rows
+            .iter()
+            .map(|row| {
+                fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| {
+                        let value = Value::decode(field, &row["f"][i]["v"]);
+                        Cell {
+                            null: value.is_null(),
+                            text: match value {
+                                Value::Null => null.to_string(),
+                                value => compact(&value, options),
+                            },
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self { columns, rows }
+    }
+}
+
+/// renders a value on a single line, nested values are rendered as `[a, b]` and `{name: value}`
+fn compact(value: &Value, options: &FormatOptions) -> String {
+    match value {
+        Value::Null => NULL.to_string(),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(|i| compact(i, options)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Struct(fields) => {
+            let pairs: Vec<String> = fields
+                .iter()
+                .map(|(name, v)| format!("{}: {}", name, compact(v, options)))
+                .collect();
+            format!("{{{}}}", pairs.join(", "))
+        }
+        other => other.to_text(options),
+    }
+}
+
+/// keeps every cell on a single line
+fn escape_control(text: &str) -> String {
+    text.chars()
+        .flat_map(|c| match c {
+            '\n' => vec!['\\', 'n'],
+            '\r' => vec!['\\', 'r'],
+            '\t' => vec!['\\', 't'],
+            c => vec![c],
+        })
+        .collect()
+}
+
+/// columns the text takes up in a terminal, wide characters such as CJK and emoji take two
+fn width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+fn truncate(text: String, max_width: usize) -> String {
+    if width(&text) <= max_width {
+        return text;
+    }
+
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let c_width = UnicodeWidthChar::width(c).unwrap_or_default();
+        if used + c_width > max_width.saturating_sub(1) {
+            break;
+        }
+        used += c_width;
+        truncated.push(c);
+    }
+    truncated.push('…');
+    truncated
+}
+
+fn pad(text: &str, width: usize, right_align: bool) -> String {
+    let padding = " ".repeat(width.saturating_sub(self::width(text)));
+    if right_align {
+        format!("{}{}", padding, text)
+    } else {
+        format!("{}{}", text, padding)
+    }
+}
+
+impl QueryResponse {
+    /// renders an aligned table for display in a terminal
+    pub fn into_table(self, options: &TableOptions) -> String {
+        let grid = Grid::new(&self, &options.values, &options.null);
+
+        let header: Vec<String> = grid
+            .columns
+            .iter()
+            .map(|c| truncate(escape_control(&c.name), options.max_width))
+            .collect();
+
+        let rows: Vec<Vec<String>> = grid
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| truncate(escape_control(&cell.text), options.max_width))
+                    .collect()
+            })
+            .collect();
+
+        let widths: Vec<usize> = header
+            .iter()
+            .enumerate()
+            .map(|(i, h)| {
+                rows.iter()
+                    .map(|r| width(&r[i]))
+                    .chain(std::iter::once(width(h)))
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let border = |left: &str, middle: &str, right: &str| {
+            let lines: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+            format!("{}{}{}", left, lines.join(middle), right)
+        };
+
+        let line = |cells: Vec<String>| {
+            let cells: Vec<String> = cells.into_iter().map(|c| format!(" {} ", c)).collect();
+            format!("│{}│", cells.join("│"))
+        };
+
+        let mut output = vec![border("┌", "┬", "┐")];
+        output.push(line(
+            header
+                .iter()
+                .zip(&widths)
+                .map(|(h, w)| pad(h, *w, false))
+                .collect(),
+        ));
+        output.push(border("├", "┼", "┤"));
+
+        for (row, cells) in rows.iter().zip(&grid.rows) {
+            output.push(line(
+                row.iter()
+                    .zip(cells)
+                    .zip(grid.columns.iter().zip(&widths))
+                    // NULL stays left aligned so it stands out from numbers
+                    .map(|((text, cell), (column, w))| {
+                        let padded = pad(text, *w, column.numeric && !cell.null);
+                        match cell.null && options.dim_nulls {
+                            true => format!("\x1b[2m{}\x1b[0m", padded),
+                            false => padded,
+                        }
+                    })
+                    .collect(),
+            ));
+        }
+
+        output.push(border("└", "┴", "┘"));
+        output.push(format!(
+            "{} row{}",
+            grid.rows.len(),
+            if grid.rows.len() == 1 { "" } else { "s" }
+        ));
+
+        output.join("\n")
+    }
+
+    /// renders a github flavored markdown table, `NULL` cells are italic
+    pub fn into_markdown(self, options: &FormatOptions, null: &str) -> String {
+        let grid = Grid::new(&self, options, null);
+
+        let escape = |text: &str| escape_control(text).replace('|', "\\|");
+
+        let header: Vec<String> = grid.columns.iter().map(|c| escape(&c.name)).collect();
+        let alignment: Vec<&str> = grid
+            .columns
+            .iter()
+            .map(|c| if c.numeric { "---:" } else { "---" })
+            .collect();
+
+        let mut output = vec![
+            format!("| {} |", header.join(" | ")),
+            format!("| {} |", alignment.join(" | ")),
+        ];
+
+        for row in &grid.rows {
+            let cells: Vec<String> = row
+                .iter()
+                .map(|cell| {
+                    if cell.null && !cell.text.is_empty() {
+                        format!("_{}_", escape(&cell.text))
+                    } else {
+                        escape(&cell.text)
+                    }
+                })
+                .collect();
+            output.push(format!("| {} |", cells.join(" | ")));
+        }
+
+        output.join("\n")
+    }
+
+    /// renders an html `<table>`, `NULL` cells have the `null` class
+    pub fn into_html(self, options: &FormatOptions, null: &str) -> String {
+        let grid = Grid::new(&self, options, null);
+
+        let mut output = vec!["<table>".to_string(), "  <thead>".to_string()];
+        let header: Vec<String> = grid
+            .columns
+            .iter()
+            .map(|c| format!("<th>{}</th>", escape_html(&c.name)))
+            .collect();
+        output.push(format!("    <tr>{}</tr>", header.join("")));
+        output.push("  </thead>".to_string());
+        output.push("  <tbody>".to_string());
+
+        for row in &grid.rows {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&grid.columns)
+                .map(|(cell, column)| match (cell.null, column.numeric) {
+                    (true, _) => format!("<td class=\"null\">{}</td>", escape_html(&cell.text)),
+                    (false, true) => format!(
+                        "<td style=\"text-align: right\">{}</td>",
+                        escape_html(&cell.text)
+                    ),
+                    (false, false) => format!("<td>{}</td>", escape_html(&cell.text)),
+                })
+                .collect();
+            output.push(format!("    <tr>{}</tr>", cells.join("")));
+        }
+
+        output.push("  </tbody>".to_string());
+        output.push("</table>".to_string());
+        output.join("\n")
+    }
+}
+
+/// one line per column with its type, mode and description, nested columns are named `parent.child`
+pub fn schema(schema: &TableSchema) -> String {
+    fn flatten(prefix: &str, fields: &[TableFieldSchema], lines: &mut Vec<[String; 4]>) {
+        for field in fields {
+            let name = format!("{}{}", prefix, field.name);
+            lines.push([
+                name.clone(),
+                field.field_type.clone(),
+                field.mode.clone(),
+                field.description.clone().unwrap_or_default(),
+            ]);
+            if let Some(fields) = &field.fields {
+                flatten(&format!("{}.", name), fields, lines);
+            }
+        }
+    }
+
+    let mut lines = vec![["column", "type", "mode", "description"].map(String::from)];
+    flatten("", &schema.fields, &mut lines);
+
+    let widths: Vec<usize> = (0..3)
+        .map(|i| lines.iter().map(|l| width(&l[i])).max().unwrap_or_default())
+        .collect();
+
+    lines
+        .iter()
+        .map(|line| {
+            let padded: Vec<String> = widths
+                .iter()
+                .zip(line)
+                .map(|(w, c)| pad(c, *w, false))
+                .collect();
+            format!("{}  {}", padded.join("  "), line[3])
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{schema, TableOptions};
+    use crate::query::response::QueryResponse;
+
+    fn response() -> QueryResponse {
+        serde_json::from_value(serde_json::json!({
+            "kind": "bigquery#queryResponse",
+            "jobReference": { "projectId": "test", "jobId": "job_123", "location": "US" },
+            "jobComplete": true,
+            "schema": { "fields": [
+                { "name": "name", "type": "STRING", "mode": "NULLABLE" },
+                { "name": "total", "type": "INTEGER", "mode": "NULLABLE" },
+                { "name": "tags", "type": "STRING", "mode": "REPEATED" },
+                { "name": "owner", "type": "RECORD", "mode": "NULLABLE", "fields": [
+                    { "name": "id", "type": "INTEGER", "mode": "NULLABLE" }
+                ]}
+            ]},
+            "rows": [
+                { "f": [{ "v": "" }, { "v": "5" }, { "v": [{ "v": "a" }, { "v": "b" }] }, { "v": { "f": [{ "v": "1" }] } }] },
+                { "f": [{ "v": null }, { "v": "1234" }, { "v": [] }, { "v": null }] },
+                { "f": [{ "v": "a very long value | with a pipe" }, { "v": null }, { "v": [] }, { "v": null }] }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn renders_table() {
+        let table = response().into_table(&TableOptions {
+            max_width: 10,
+            ..Default::default()
+        });
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert_eq!(lines[1], "│ name       │ total │ tags   │ owner   │");
+        assert_eq!(lines[3], "│            │     5 │ [a, b] │ {id: 1} │");
+        assert_eq!(lines[4], "│ NULL       │  1234 │ []     │ NULL    │");
+        assert_eq!(lines[5], "│ a very lo… │ NULL  │ []     │ NULL    │");
+        assert_eq!(lines[7], "3 rows");
+    }
+
+    #[test]
+    fn aligns_wide_characters() {
+        let mut response = response();
+        response.rows[0]["f"][0]["v"] = serde_json::json!("東京🙂");
+        response.rows[2]["f"][0]["v"] = serde_json::json!("東京都渋谷区");
+
+        let table = response.into_table(&TableOptions {
+            max_width: 10,
+            ..Default::default()
+        });
+        let lines: Vec<&str> = table.lines().collect();
+
+        // a wide character that does not fit is left out, so the column is one narrower
+        assert_eq!(lines[1], "│ name      │ total │ tags   │ owner   │");
+        assert_eq!(lines[3], "│ 東京🙂    │     5 │ [a, b] │ {id: 1} │");
+        assert_eq!(lines[5], "│ 東京都渋… │ NULL  │ []     │ NULL    │");
+    }
+
+    #[test]
+    fn sets_nulls_apart_from_strings() {
+        // the first row holds the string 'NULL', the second a NULL
+        let response = || {
+            let mut response = response();
+            response.rows[0]["f"][0]["v"] = serde_json::json!("NULL");
+            response
+        };
+
+        let table = response().into_table(&TableOptions {
+            dim_nulls: true,
+            ..Default::default()
+        });
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[3].starts_with("│ NULL "));
+        assert!(lines[4].starts_with("│ \x1b[2mNULL"));
+
+        let table = response().into_table(&TableOptions {
+            null: String::new(),
+            ..Default::default()
+        });
+        assert!(table.lines().nth(4).unwrap().starts_with("│      "));
+
+        let markdown = response().into_markdown(&Default::default(), "");
+        assert_eq!(
+            markdown.lines().nth(2).unwrap(),
+            "| NULL | 5 | [a, b] | {id: 1} |"
+        );
+        assert_eq!(markdown.lines().nth(3).unwrap(), "|  | 1234 | [] |  |");
+    }
+
+    #[test]
+    fn renders_markdown() {
+        let markdown = response().into_markdown(&Default::default(), super::NULL);
+        let lines: Vec<&str> = markdown.lines().collect();
+
+        assert_eq!(lines[1], "| --- | ---: | --- | --- |");
+        assert_eq!(lines[3], "| _NULL_ | 1234 | [] | _NULL_ |");
+        assert!(lines[4].contains("a very long value \\| with a pipe"));
+    }
+
+    #[test]
+    fn renders_html() {
+        let html = response().into_html(&Default::default(), super::NULL);
+        assert!(html.contains("<th>name</th>"));
+        assert!(html.contains("<td class=\"null\">NULL</td>"));
+        assert!(html.contains("<td style=\"text-align: right\">1234</td>"));
+    }
+
+    #[test]
+    fn renders_schema() {
+        let text = schema(&response().schema.unwrap());
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[0], "column    type     mode      description");
+        assert_eq!(lines[3], "tags      STRING   REPEATED");
+        assert_eq!(lines[5], "owner.id  INTEGER  NULLABLE");
+    }
+}
@@ -0,0 +1,313 @@
+//! multi-statement queries run each statement as a child job of the script's job
+//!
+//! <https://cloud.google.com/bigquery/docs/multi-statement-queries>
+
+use crate::{
+    api::{Client, Error},
+    job::Job,
+    query::response::retry,
+};
+
+/// whether the query has more than one statement, semicolons in strings and comments are ignored
+pub fn is_script(query: &str) -> bool {
+    keywords(query).len() > 1
+}
+
+/// the first word of each statement in upper case, semicolons in strings and comments are ignored
+///
+/// a statement that starts with something other than a word, e.g. `(`, has that character instead
+pub fn keywords(query: &str) -> Vec<String> {
+    lex(query).0
+}
+
+/// whether the last statement ends with a `;`, followed by nothing but whitespace and comments
+///
+/// a `;` in a string or comment, or in one that is still open, does not end it
+pub fn is_terminated(query: &str) -> bool {
+    lex(query).1
+}
+
+/// the keywords of the statements, and whether the query is terminated
+fn lex(query: &str) -> (Vec<String>, bool) {
+    let mut keywords = Vec::new();
+    let mut chars = query.chars().peekable();
+    // whether the next word starts a statement, after the start of the query or a `;`
+    let mut starts = true;
+    // whether a `;` was the last thing outside of comments
+    let mut terminated = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '-' if chars.peek() == Some(&'-') => {
+                chars.by_ref().take_while(|c| *c != '\n').for_each(drop);
+            }
+            '#' => chars.by_ref().take_while(|c| *c != '\n').for_each(drop),
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        closed = true;
+                        break;
+                    }
+                    previous = c;
+                }
+                terminated &= closed;
+            }
+            ';' => {
+                starts = true;
+                terminated = true;
+            }
+            c if c.is_whitespace() => {}
+            '\'' | '"' | '`' => {
+                if starts {
+                    keywords.push(c.to_string());
+                    starts = false;
+                }
+                terminated = false;
+                while let Some(next) = chars.next() {
+                    match next {
+                        '\\' => {
+                            chars.next();
+                        }
+                        next if next == c => break,
+                        _ => {}
+                    }
+                }
+            }
+            _ if starts => {
+                let mut keyword = c.to_ascii_uppercase().to_string();
+                if c.is_alphanumeric() || c == '_' {
+                    while let Some(next) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                        keyword.push(next.to_ascii_uppercase());
+                    }
+                }
+                keywords.push(keyword);
+                starts = false;
+                terminated = false;
+            }
+            _ => terminated = false,
+        }
+    }
+
+    (keywords, terminated)
+}
+
+/// polls the job until it is done
+pub fn wait(client: &Client, job: Job) -> Result<Job, Error> {
+    if job.is_done() {
+        return Ok(job);
+    }
+
+    let Some(job_id) = job.job_reference.job_id.clone() else {
+        return Err(Error::new("no id found for incomplete job"));
+    };
+    let location = job.job_reference.location.clone();
+
+    retry(
+        || match client.jobs_get(&job_id, Some(&location)) {
+            Ok(job) if !job.is_done() => None,
+            result => Some(result),
+        },
+        None,
+    )
+}
+
+/// the statements of a script in the order they ran, conditions and default values are left out
+pub fn statements(client: &Client, script: &Job) -> Result<Vec<Job>, Error> {
+    let Some(job_id) = &script.job_reference.job_id else {
+        return Ok(Vec::new());
+    };
+
+    let mut jobs = Vec::new();
+    let mut page_token = None;
+    loop {
+        let page = client.jobs_list(job_id, page_token.as_deref())?;
+        jobs.extend(page.jobs);
+
+        page_token = page.next_page_token;
+        if page_token.is_none() {
+            break;
+        }
+    }
+
+    jobs.retain(|job| {
+        job.statistics
+            .as_ref()
+            .and_then(|s| s.script_statistics.as_ref())
+            .and_then(|s| s.evaluation_kind.as_deref())
+            != Some("EXPRESSION")
+    });
+    jobs.sort_by_key(|job| {
+        let created = job
+            .statistics
+            .as_ref()
+            .and_then(|s| crate::job::parse(&s.creation_time));
+        (created, job.job_reference.job_id.clone())
+    });
+    Ok(jobs)
+}
+
+/// where a statement starts in the script, as a line and column counted from 1
+pub fn position(statement: &Job) -> Option<(u32, u32)> {
+    let statistics = statement.statistics.as_ref()?.script_statistics.as_ref()?;
+    // the last frame is the script itself, the others are procedures it called
+    let frame = statistics.stack_frames.last()?;
+    Some((frame.start_line?, frame.start_column?))
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    /// counted from 1, `None` when the script failed before running a statement
+    pub statement: Option<usize>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.statement {
+            Some(statement) => write!(f, "statement {} failed", statement)?,
+            None => write!(f, "script failed")?,
+        }
+
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, " at line {}, column {}", line, column)?;
+        }
+
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// the statement that made the script fail
+pub fn failure(script: &Job, statements: &[Job]) -> Option<Failure> {
+    let error = script.error()?;
+
+    let Some(index) = statements.iter().position(|s| s.error().is_some()) else {
+        // e.g. a syntax error, which is found before any statement runs
+        let (line, column) = location(&error.message).unzip();
+        return Some(Failure {
+            statement: None,
+            line,
+            column,
+            message: error.message.clone(),
+        });
+    };
+
+    let statement = &statements[index];
+    // the error points into the statement, the frame only at its start
+    let (line, column) = statement
+        .error()
+        .and_then(|e| location(&e.message))
+        .or(position(statement))
+        .unzip();
+
+    Some(Failure {
+        statement: Some(index + 1),
+        line,
+        column,
+        message: statement.error()?.message.clone(),
+    })
+}
+
+/// error messages end with the position they refer to, e.g. `Unrecognized name: x at [3:8]`
+fn location(message: &str) -> Option<(u32, u32)> {
+    let inner = message.trim_end().strip_suffix(']')?;
+    let (line, column) = inner[inner.rfind('[')? + 1..].split_once(':')?;
+    Some((line.parse().ok()?, column.parse().ok()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{failure, is_script, is_terminated, keywords, Failure};
+    use crate::job::Job;
+
+    #[test]
+    fn detects_scripts() {
+        assert!(!is_script("SELECT 1"));
+        assert!(!is_script("SELECT 1;\n-- done\n"));
+        assert!(!is_script("SELECT ';' AS a, \"x;y\" /* ; */ ; # trailing"));
+        assert!(is_script("DECLARE x INT64 DEFAULT 1;\nSELECT x;"));
+        assert!(is_script("SELECT 1; SELECT 'a'"));
+    }
+
+    #[test]
+    fn finds_the_end_of_statements() {
+        assert!(is_terminated("SELECT 1;"));
+        assert!(is_terminated("SELECT 1; -- done\n"));
+        assert!(is_terminated("SELECT ';' AS a; /* done */"));
+        assert!(!is_terminated("SELECT 1 -- not yet;"));
+        assert!(!is_terminated("SELECT 'a;"));
+        assert!(!is_terminated("SELECT 1; /* still open;"));
+        assert!(!is_terminated("SELECT 1; SELECT 2"));
+        assert!(!is_terminated(""));
+    }
+
+    #[test]
+    fn finds_keywords() {
+        assert_eq!(
+            keywords("declare n INT64 DEFAULT 3; -- INSERT\nSELECT ';' /* ; DELETE */; (SELECT 1)"),
+            ["DECLARE", "SELECT", "("]
+        );
+        assert_eq!(
+            keywords("SET x = 1;\n  insert_log()"),
+            ["SET", "INSERT_LOG"]
+        );
+        assert!(keywords(" -- nothing\n").is_empty());
+    }
+
+    #[test]
+    fn finds_failed_statement() {
+        let job = |value: serde_json::Value| -> Job { serde_json::from_value(value).unwrap() };
+        let reference =
+            serde_json::json!({ "projectId": "test", "jobId": "script_1", "location": "US" });
+        let error = serde_json::json!({ "reason": "invalidQuery", "message": "Division by zero: 1 / 0 at [3:8]" });
+
+        let script = job(serde_json::json!({
+            "jobReference": reference,
+            "status": { "state": "DONE", "errorResult": error }
+        }));
+        let statements = vec![
+            job(serde_json::json!({
+                "jobReference": reference,
+                "status": { "state": "DONE" },
+                "statistics": { "scriptStatistics": { "evaluationKind": "STATEMENT",
+                    "stackFrames": [{ "startLine": 1, "startColumn": 1 }] } }
+            })),
+            job(serde_json::json!({
+                "jobReference": reference,
+                "status": { "state": "DONE", "errorResult": error },
+                "statistics": { "scriptStatistics": { "evaluationKind": "STATEMENT",
+                    "stackFrames": [{ "startLine": 3, "startColumn": 1 }] } }
+            })),
+        ];
+
+        let failed = failure(&script, &statements).unwrap();
+        assert_eq!(
+            failed,
+            Failure {
+                statement: Some(2),
+                line: Some(3),
+                column: Some(8),
+                message: "Division by zero: 1 / 0 at [3:8]".to_string(),
+            }
+        );
+        assert_eq!(
+            failed.to_string(),
+            "statement 2 failed at line 3, column 8: Division by zero: 1 / 0 at [3:8]"
+        );
+
+        // without a position in the message, the statement starts where its frame does
+        let mut statements = statements;
+        statements[1].status.as_mut().unwrap().error_result =
+            Some(serde_json::from_value(serde_json::json!({ "reason": "invalidQuery", "message": "Division by zero: 1 / 0" })).unwrap());
+        let failed = failure(&script, &statements).unwrap();
+        assert_eq!((failed.line, failed.column), (Some(3), Some(1)));
+
+        let failed = failure(&script, &[]).unwrap();
+        assert_eq!(failed.statement, None);
+        assert_eq!((failed.line, failed.column), (Some(3), Some(8)));
+    }
+}
@@ -0,0 +1,83 @@
+//! <https://cloud.google.com/bigquery/docs/sessions-intro>
+//!
+//! temporary tables, variables and transactions persist between the queries of a session
+
+use crate::{
+    api::{Client, Error},
+    query::{request::QueryRequestBuilder, response::QueryResponse},
+};
+
+pub struct Session<'a> {
+    client: &'a Client,
+    id: String,
+    location: String,
+}
+
+impl<'a> Session<'a> {
+    /// creates a new session, `location` defaults to the location of the project
+    pub fn start(client: &'a Client, location: Option<String>) -> Result<Self, Error> {
+        let mut request = QueryRequestBuilder::new("SELECT 1".to_string()).create_session();
+        if let Some(location) = location {
+            request = request.location(location);
+        }
+
+        let response = client.jobs_query(request.build())?;
+        let Some(session) = response.session_info else {
+            return Err(Error::new("no session was created"));
+        };
+
+        Ok(Self {
+            client,
+            id: session.session_id,
+            location: response.job_reference.location,
+        })
+    }
+
+    /// continues a session created earlier, possibly by another process
+    pub fn attach(client: &'a Client, id: String, location: String) -> Self {
+        Self {
+            client,
+            id,
+            location,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    /// runs the query inside the session
+    pub fn query(&self, request: QueryRequestBuilder) -> Result<QueryResponse, Error> {
+        let request = request
+            .session_id(self.id.clone())
+            .location(self.location.clone())
+            .build();
+        self.client.jobs_query(request)
+    }
+
+    fn run(&self, statement: &str) -> Result<QueryResponse, Error> {
+        self.query(QueryRequestBuilder::new(statement.to_string()))
+    }
+
+    /// the transaction spans every following query until `commit` or `rollback`
+    pub fn begin_transaction(&self) -> Result<QueryResponse, Error> {
+        self.run("BEGIN TRANSACTION")
+    }
+
+    pub fn commit(&self) -> Result<QueryResponse, Error> {
+        self.run("COMMIT TRANSACTION")
+    }
+
+    pub fn rollback(&self) -> Result<QueryResponse, Error> {
+        self.run("ROLLBACK TRANSACTION")
+    }
+
+    /// terminates the session right away instead of after 24 hours of inactivity
+    pub fn end(self) -> Result<QueryResponse, Error> {
+        self.run("CALL BQ.ABORT_SESSION()")
+    }
+}
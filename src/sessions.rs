@@ -0,0 +1,136 @@
+//! sessions started by `bq-rs session start`, kept so later invocations can attach to them
+
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// bigquery terminates sessions after 24 hours of inactivity
+const IDLE_TIMEOUT_SECS: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Sessions {
+    #[serde(default)]
+    pub sessions: Vec<Saved>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Saved {
+    pub id: String,
+    pub name: Option<String>,
+    pub project_id: String,
+    pub location: String,
+    /// seconds since the epoch
+    pub started_at: i64,
+    pub last_used_at: i64,
+}
+
+impl Sessions {
+    fn path() -> anyhow::Result<PathBuf> {
+        let mut path = Config::cache_dir()?;
+        path.push("sessions.json");
+        Ok(path)
+    }
+
+    /// sessions that bigquery has already terminated are left out
+    pub fn load() -> anyhow::Result<Self> {
+        let mut sessions: Self = match std::fs::read_to_string(Self::path()?) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        sessions.prune(chrono::Utc::now().timestamp());
+        Ok(sessions)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn prune(&mut self, now: i64) {
+        self.sessions
+            .retain(|s| now - s.last_used_at < IDLE_TIMEOUT_SECS);
+    }
+
+    /// looks a session up by its name or id
+    pub fn find(&self, session: &str) -> Option<&Saved> {
+        self.sessions
+            .iter()
+            .find(|s| s.name.as_deref() == Some(session) || s.id == session)
+    }
+
+    /// like `find`, sessions can only be used from the project that started them
+    pub fn get(&self, session: &str, project_id: &str) -> anyhow::Result<Saved> {
+        let Some(saved) = self.find(session) else {
+            anyhow::bail!("session `{}` does not exist or has expired", session);
+        };
+
+        if saved.project_id != project_id {
+            anyhow::bail!(
+                "session `{}` belongs to project {}",
+                session,
+                saved.project_id
+            );
+        }
+
+        Ok(saved.clone())
+    }
+
+    pub fn add(&mut self, saved: Saved) -> anyhow::Result<()> {
+        if let Some(name) = &saved.name {
+            if self.find(name).is_some() {
+                anyhow::bail!("a session named `{}` already exists", name);
+            }
+        }
+
+        self.sessions.push(saved);
+        Ok(())
+    }
+
+    pub fn touch(&mut self, id: &str) {
+        let now = chrono::Utc::now().timestamp();
+        for session in self.sessions.iter_mut().filter(|s| s.id == id) {
+            session.last_used_at = now;
+        }
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        self.sessions.retain(|s| s.id != id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Saved, Sessions, IDLE_TIMEOUT_SECS};
+
+    fn saved(id: &str, name: Option<&str>, last_used_at: i64) -> Saved {
+        Saved {
+            id: id.to_string(),
+            name: name.map(String::from),
+            project_id: "test".to_string(),
+            location: "US".to_string(),
+            started_at: 0,
+            last_used_at,
+        }
+    }
+
+    #[test]
+    fn finds_and_prunes_sessions() {
+        let mut sessions = Sessions::default();
+        sessions.add(saved("abc", Some("etl"), 0)).unwrap();
+        sessions.add(saved("def", None, IDLE_TIMEOUT_SECS)).unwrap();
+        assert!(sessions.add(saved("ghi", Some("etl"), 0)).is_err());
+
+        assert_eq!(sessions.find("etl").unwrap().id, "abc");
+        assert_eq!(sessions.find("def").unwrap().id, "def");
+
+        sessions.prune(IDLE_TIMEOUT_SECS + 1);
+        assert!(sessions.find("etl").is_none());
+        assert!(sessions.find("def").is_some());
+    }
+}
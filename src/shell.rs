@@ -0,0 +1,332 @@
+//! interactive sql shell
+//!
+//! statements may span several lines and end with `;`, they all run inside a single
+//! bigquery session so temporary tables and variables persist between them
+
+use crate::{
+    cli::{Defaults, Format, Policy},
+    config::Config,
+};
+use bq_rs::{
+    api::Client, catalog::Catalog, query::response::QueryResponse, session::Session,
+    table::TableReference, value::FormatOptions,
+};
+use clap::ValueEnum;
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::FileHistory, validate::Validator, Editor, Helper,
+};
+use std::{cell::RefCell, path::PathBuf, rc::Rc, time::Instant};
+
+const PROMPT: &str = "bq> ";
+const CONTINUATION: &str = " -> ";
+
+const HELP: &str = r"statements end with `;`, meta-commands:
+  \d <dataset.table>  describe the schema of a table
+  \format [format]    show or change the output format
+  \dry                toggle dry runs, which only estimate the bytes processed
+  \project [id]       show or change the project, this starts a new session
+  \timing             toggle printing how long each statement took
+  \q                  quit";
+
+pub struct Shell {
+    client: Client,
+    token: String,
+    project_id: String,
+    defaults: Defaults,
+    policy: Policy,
+    /// id and location of the session created by the first statement
+    session: Option<(String, String)>,
+    format: Format,
+    values: FormatOptions,
+    /// text of NULL values, `None` keeps the default of the format
+    null: Option<String>,
+    dry_run: bool,
+    timing: bool,
+    completion: Rc<Completion>,
+}
+
+/// state shared with the line editor for completions
+#[derive(Default)]
+struct Completion {
+    catalog: RefCell<Option<Catalog>>,
+    /// the lines of the statement typed so far, they tell which tables columns come from
+    statement: RefCell<String>,
+}
+
+struct SqlHelper(Rc<Completion>);
+
+impl Completer for SqlHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let catalog = self.0.catalog.borrow();
+        let Some(catalog) = catalog.as_ref() else {
+            return Ok((pos, Vec::new()));
+        };
+
+        let text = format!("{}\n{}", self.0.statement.borrow(), &line[..pos]);
+        let (start, candidates) = catalog.complete(&text);
+        Ok((start.saturating_sub(text.len() - pos), candidates))
+    }
+}
+
+impl Hinter for SqlHelper {
+    type Hint = String;
+}
+
+impl Highlighter for SqlHelper {}
+
+impl Validator for SqlHelper {}
+
+impl Helper for SqlHelper {}
+
+impl Shell {
+    pub fn new(
+        token: String,
+        project_id: String,
+        defaults: Defaults,
+        policy: Policy,
+        format: Format,
+        null: Option<String>,
+        catalog: Catalog,
+    ) -> Self {
+        let completion = Completion {
+            catalog: RefCell::new(Some(catalog)),
+            ..Default::default()
+        };
+
+        Self {
+            client: Client::bq_client(token.clone(), &project_id),
+            token,
+            project_id,
+            defaults,
+            policy,
+            session: None,
+            format,
+            values: FormatOptions::default(),
+            null,
+            dry_run: false,
+            timing: false,
+            completion: Rc::new(completion),
+        }
+    }
+
+    pub fn run(mut self, history: Option<PathBuf>) -> anyhow::Result<()> {
+        let mut editor: Editor<SqlHelper, FileHistory> = Editor::new()?;
+        editor.set_helper(Some(SqlHelper(self.completion.clone())));
+        if let Some(history) = &history {
+            // the file does not exist on first use
+            let _ = editor.load_history(history);
+        }
+
+        let mut buffer = String::new();
+        loop {
+            let prompt = if buffer.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION
+            };
+            let line = match editor.readline(prompt) {
+                Ok(line) => line,
+                // ctrl-c discards the statement being typed
+                Err(ReadlineError::Interrupted) => {
+                    buffer.clear();
+                    self.completion.statement.borrow_mut().clear();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            };
+
+            if buffer.is_empty() && line.trim_start().starts_with('\\') {
+                editor.add_history_entry(line.as_str())?;
+                if !self.meta(line.trim()) {
+                    break;
+                }
+                continue;
+            }
+
+            if !buffer.is_empty() {
+                buffer.push('\n');
+            }
+            buffer.push_str(&line);
+
+            if !bq_rs::script::is_terminated(&buffer) {
+                *self.completion.statement.borrow_mut() = buffer.clone();
+                continue;
+            }
+
+            self.completion.statement.borrow_mut().clear();
+            editor.add_history_entry(buffer.as_str())?;
+            let statement = std::mem::take(&mut buffer);
+            let statement = statement.trim().trim_end_matches(';');
+            if !statement.is_empty() {
+                Self::report(self.execute(statement));
+            }
+        }
+
+        if let Some(history) = &history {
+            if let Some(parent) = history.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            editor.save_history(history)?;
+        }
+
+        if self.session.is_some() {
+            Self::report(self.abort_session());
+        }
+
+        Ok(())
+    }
+
+    /// reports a failed statement or command without ending the shell
+    fn report(result: anyhow::Result<()>) {
+        if let Err(e) = result {
+            eprintln!("error: {}", e);
+        }
+    }
+
+    /// returns `false` when the shell should exit
+    fn meta(&mut self, line: &str) -> bool {
+        let (command, argument) = match line.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, Some(argument.trim())),
+            None => (line, None),
+        };
+
+        match (command, argument) {
+            ("\\q" | "\\quit", _) => return false,
+            ("\\?" | "\\h" | "\\help", _) => println!("{}", HELP),
+            ("\\d", Some(table)) => Self::report(self.describe(table)),
+            ("\\format", None) => {
+                if let Some(format) = self.format.to_possible_value() {
+                    println!("{}", format.get_name());
+                }
+            }
+            ("\\format", Some(format)) => match Format::from_str(format, true) {
+                Ok(Format::Parquet | Format::Arrow) => {
+                    eprintln!("error: {} can only be written to a file", format)
+                }
+                Ok(format) => self.format = format,
+                Err(e) => eprintln!("error: {}", e),
+            },
+            ("\\dry", _) => {
+                self.dry_run = !self.dry_run;
+                println!("dry run is {}", if self.dry_run { "on" } else { "off" });
+            }
+            ("\\project", None) => println!("{}", self.project_id),
+            ("\\project", Some(project_id)) => {
+                if self.session.is_some() {
+                    Self::report(self.abort_session());
+                }
+                self.project_id = project_id.to_string();
+                self.client = Client::bq_client(self.token.clone(), project_id);
+                // completions use the cache of the new project until it is refreshed
+                *self.completion.catalog.borrow_mut() = Config::catalog_path(project_id)
+                    .ok()
+                    .and_then(|path| Catalog::load(&path));
+            }
+            ("\\timing", _) => {
+                self.timing = !self.timing;
+                println!("timing is {}", if self.timing { "on" } else { "off" });
+            }
+            _ => eprintln!("unknown command `{}`, try \\?", line),
+        }
+
+        true
+    }
+
+    fn execute(&mut self, statement: &str) -> anyhow::Result<()> {
+        let started = Instant::now();
+        let mut request = self
+            .defaults
+            .request(statement.to_string(), &self.project_id)
+            .use_int64_timestamp();
+
+        if let Some((session_id, _)) = &self.session {
+            request = request.session_id(session_id.clone());
+        }
+
+        // dry runs join an existing session but never start one
+        if self.dry_run {
+            request = request.dry_run();
+        } else {
+            self.policy.check(&self.client, &request)?;
+            if self.session.is_none() {
+                request = request.create_session();
+            }
+        }
+
+        let response = self.client.jobs_query(request.build())?;
+
+        if self.session.is_none() {
+            self.session = response.session_info.as_ref().map(|s| {
+                (
+                    s.session_id.clone(),
+                    response.job_reference.location.clone(),
+                )
+            });
+        }
+
+        print!("{}", crate::cli::line_ended(self.render(response)));
+
+        if self.timing {
+            println!("time: {:.3}s", started.elapsed().as_secs_f64());
+        }
+
+        Ok(())
+    }
+
+    fn render(&self, response: QueryResponse) -> String {
+        if self.dry_run {
+            let bytes = response
+                .total_bytes_processed
+                .as_deref()
+                .and_then(|b| b.parse().ok())
+                .unwrap_or_default();
+            return format!("this query will process {}", bq_rs::job::bytes(bytes));
+        }
+
+        // DDL and DML statements do not return rows
+        if response.schema.is_none() {
+            return match &response.num_dml_affected_rows {
+                Some(rows) => format!("{} rows affected", rows),
+                None => "OK".to_string(),
+            };
+        }
+
+        crate::cli::render(
+            response,
+            self.format,
+            self.values.clone(),
+            self.null.as_deref(),
+        )
+    }
+
+    fn describe(&self, table: &str) -> anyhow::Result<()> {
+        let Some(reference) = TableReference::parse(table, &self.project_id) else {
+            anyhow::bail!("expected `dataset.table` or `project.dataset.table`");
+        };
+
+        let table = self.client.tables_get_reference(&reference)?;
+        match &table.schema {
+            Some(schema) => println!("{}", bq_rs::render::schema(schema)),
+            None => println!("{} has no schema", table.table_reference.table_id),
+        }
+
+        Ok(())
+    }
+
+    fn abort_session(&mut self) -> anyhow::Result<()> {
+        if let Some((id, location)) = self.session.take() {
+            Session::attach(&self.client, id, location).end()?;
+        }
+
+        Ok(())
+    }
+}
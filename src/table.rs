@@ -0,0 +1,121 @@
+//! <https://cloud.google.com/bigquery/docs/reference/rest/v2/tables>
+
+use crate::query::response::{JobReference, QueryResponse, TableSchema};
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Table {
+    pub id: Option<String>,
+    pub table_reference: TableReference,
+    /// `TABLE`, `VIEW`, `EXTERNAL`, `MATERIALIZED_VIEW` or `SNAPSHOT`
+    #[serde(rename = "type")]
+    pub table_type: Option<String>,
+    pub description: Option<String>,
+    pub schema: Option<TableSchema>,
+    pub num_rows: Option<String>,
+    pub num_bytes: Option<String>,
+    /// milliseconds since the epoch
+    pub last_modified_time: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableReference {
+    pub project_id: String,
+    pub dataset_id: String,
+    pub table_id: String,
+}
+
+impl TableReference {
+    /// a `dataset.table` or `project.dataset.table` reference, optionally quoted with backticks
+    ///
+    /// only domain-scoped projects, e.g. `example.com:project`, may contain a `.`
+    pub fn parse(reference: &str, default_project: &str) -> Option<Self> {
+        let parts: Vec<&str> = reference.trim_matches('`').rsplitn(3, '.').collect();
+        let (project, dataset, table) = match parts[..] {
+            [table, dataset] => (default_project, dataset, table),
+            [table, dataset, project] if !project.contains('.') || project.contains(':') => {
+                (project, dataset, table)
+            }
+            _ => return None,
+        };
+
+        if [project, dataset, table].iter().any(|part| part.is_empty()) {
+            return None;
+        }
+
+        Some(Self {
+            project_id: project.to_string(),
+            dataset_id: dataset.to_string(),
+            table_id: table.to_string(),
+        })
+    }
+}
+
+/// <https://cloud.google.com/bigquery/docs/reference/rest/v2/tabledata/list>
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableDataList {
+    pub total_rows: Option<String>,
+    pub page_token: Option<String>,
+    /// rows use the same `f` and `v` encoding as query results
+    #[serde(default)]
+    pub rows: Vec<serde_json::Value>,
+}
+
+impl TableDataList {
+    /// pairs the rows with the schema of their table, so they render like query results
+    pub fn into_response(self, table: Table) -> QueryResponse {
+        QueryResponse {
+            kind: "bigquery#tableDataList".to_string(),
+            etag: None,
+            schema: table.schema,
+            job_reference: JobReference {
+                project_id: table.table_reference.project_id,
+                job_id: None,
+                location: String::new(),
+            },
+            total_rows: self.total_rows,
+            page_token: self.page_token,
+            rows: self.rows,
+            total_bytes_processed: None,
+            job_complete: true,
+            errors: None,
+            cache_hit: false,
+            num_dml_affected_rows: None,
+            session_info: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TableReference;
+
+    #[test]
+    fn parses_references() {
+        let table = TableReference::parse("sales.orders", "default").unwrap();
+        assert_eq!(
+            (
+                table.project_id.as_str(),
+                table.dataset_id.as_str(),
+                table.table_id.as_str()
+            ),
+            ("default", "sales", "orders")
+        );
+
+        let table = TableReference::parse("`other.sales.orders`", "default").unwrap();
+        assert_eq!(table.project_id, "other");
+        assert_eq!(table.dataset_id, "sales");
+        assert_eq!(table.table_id, "orders");
+
+        let table = TableReference::parse("example.com:other.sales.orders", "default").unwrap();
+        assert_eq!(table.project_id, "example.com:other");
+        assert_eq!(table.dataset_id, "sales");
+        assert_eq!(table.table_id, "orders");
+
+        assert!(TableReference::parse("orders", "default").is_none());
+        assert!(TableReference::parse("a.b.c.d", "default").is_none());
+        assert!(TableReference::parse("sales.", "default").is_none());
+    }
+}
@@ -0,0 +1,446 @@
+//! type aware decoding of the cells returned by the REST API, driven by `TableFieldSchema.field_type`
+//!
+//! <https://cloud.google.com/bigquery/docs/reference/standard-sql/data-types>
+
+use crate::query::response::TableFieldSchema;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, Utc};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int64(i64),
+    Float64(f64),
+    /// `NUMERIC` and `BIGNUMERIC` keep their exact decimal text
+    Numeric(String),
+    String(String),
+    /// base64 encoded
+    Bytes(String),
+    Timestamp(DateTime<Utc>),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    DateTime(NaiveDateTime),
+    /// well known text
+    Geography(String),
+    Json(serde_json::Value),
+    Interval(Interval),
+    /// `Null` bounds are `UNBOUNDED`
+    Range(Box<Value>, Box<Value>),
+    Array(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum GeographyFormat {
+    #[default]
+    Wkt,
+    GeoJson,
+}
+
+/// controls how values are turned into text
+#[derive(Debug, Clone, Default)]
+pub struct FormatOptions {
+    /// timestamps are rendered in UTC unless a timezone is given
+    pub timezone: Option<chrono_tz::Tz>,
+    pub geography: GeographyFormat,
+}
+
+/// <https://cloud.google.com/bigquery/docs/reference/standard-sql/data-types#interval_type>
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Interval {
+    pub months: i64,
+    pub days: i64,
+    pub micros: i64,
+}
+
+impl Value {
+    /// decodes a raw cell, values that do not match their declared type are kept as strings
+    pub fn decode(field: &TableFieldSchema, raw: &serde_json::Value) -> Value {
+        use serde_json::Value as Json;
+
+        match raw {
+            Json::Null => Value::Null,
+            Json::Array(items) if field.is_repeated() => {
+                let element = TableFieldSchema {
+                    mode: "NULLABLE".to_string(),
+                    ..field.clone()
+                };
+                Value::Array(
+                    items
+                        .iter()
+                        .map(|i| Value::decode(&element, &i["v"]))
+                        .collect(),
+                )
+            }
+            Json::Object(record) => {
+                let values = record.get("f").and_then(|f| f.as_array());
+                Value::Struct(
+                    field
+                        .fields
+                        .iter()
+                        .flatten()
+                        .zip(values.into_iter().flatten())
+                        .map(|(child, v)| (child.name.clone(), Value::decode(child, &v["v"])))
+                        .collect(),
+                )
+            }
+            Json::String(s) => Self::scalar(field, s).unwrap_or_else(|| Value::String(s.clone())),
+            Json::Bool(b) => Value::Bool(*b),
+            Json::Number(n) => {
+                Self::scalar(field, &n.to_string()).unwrap_or_else(|| Value::String(n.to_string()))
+            }
+            Json::Array(_) => Value::String(raw.to_string()),
+        }
+    }
+
+    fn scalar(field: &TableFieldSchema, s: &str) -> Option<Value> {
+        let value = match field.field_type.as_str() {
+            "INTEGER" | "INT64" => Value::Int64(s.parse().ok()?),
+            "FLOAT" | "FLOAT64" => Value::Float64(parse_float(s)?),
+            "NUMERIC" | "BIGNUMERIC" => Value::Numeric(s.to_string()),
+            "BOOLEAN" | "BOOL" => Value::Bool(s.eq_ignore_ascii_case("true")),
+            "BYTES" => Value::Bytes(s.to_string()),
+            "TIMESTAMP" => Value::Timestamp(DateTime::from_timestamp_micros(timestamp_micros(s)?)?),
+            "DATE" => Value::Date(NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?),
+            "TIME" => Value::Time(NaiveTime::parse_from_str(s, "%H:%M:%S%.f").ok()?),
+            "DATETIME" => Value::DateTime(
+                NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+                    .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f"))
+                    .ok()?,
+            ),
+            "GEOGRAPHY" => Value::Geography(s.to_string()),
+            "JSON" => Value::Json(serde_json::from_str(s).ok()?),
+            "INTERVAL" => Value::Interval(Interval::parse(s)?),
+            "RANGE" => Self::range(field, s)?,
+            _ => Value::String(s.to_string()),
+        };
+
+        Some(value)
+    }
+
+    /// ranges arrive as `[start, end)` where either bound may be `UNBOUNDED`
+    fn range(field: &TableFieldSchema, s: &str) -> Option<Value> {
+        let inner = s.strip_prefix('[')?.strip_suffix(')')?;
+        let (start, end) = inner.split_once(',')?;
+
+        let element = TableFieldSchema {
+            field_type: field
+                .range_element_type
+                .as_ref()
+                .map(|r| r.element_type.clone())
+                .unwrap_or_default(),
+            mode: "NULLABLE".to_string(),
+            ..field.clone()
+        };
+
+        let bound = |s: &str| match s.trim() {
+            "UNBOUNDED" => Some(Value::Null),
+            s => Self::scalar(&element, s),
+        };
+
+        Some(Value::Range(Box::new(bound(start)?), Box::new(bound(end)?)))
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// renders the value as text, nested values are rendered as JSON
+    ///
+    /// `NULL` renders as an empty string
+    pub fn to_text(&self, options: &FormatOptions) -> String {
+        match self {
+            Value::Null => String::new(),
+            Value::Bool(b) => b.to_string(),
+            Value::Int64(i) => i.to_string(),
+            Value::Float64(f) => format_float(*f),
+            Value::Numeric(s) | Value::String(s) | Value::Bytes(s) => s.clone(),
+            Value::Timestamp(t) => match options.timezone {
+                Some(tz) => t
+                    .with_timezone(&tz)
+                    .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                None => t.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            },
+            Value::Date(d) => d.format("%Y-%m-%d").to_string(),
+            Value::Time(t) => t.format("%H:%M:%S%.f").to_string(),
+            Value::DateTime(d) => d.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+            Value::Geography(wkt) => match options.geography {
+                GeographyFormat::Wkt => wkt.clone(),
+                GeographyFormat::GeoJson => self.to_json(options).to_string(),
+            },
+            Value::Json(json) => json.to_string(),
+            Value::Interval(interval) => interval.to_string(),
+            Value::Range(start, end) => {
+                let bound = |v: &Value| match v {
+                    Value::Null => "UNBOUNDED".to_string(),
+                    v => v.to_text(options),
+                };
+                format!("[{}, {})", bound(start), bound(end))
+            }
+            Value::Array(_) | Value::Struct(_) => self.to_json(options).to_string(),
+        }
+    }
+
+    /// converts the value into JSON
+    ///
+    /// numbers that cannot be represented exactly (`NUMERIC`, `NaN`) are kept as strings
+    pub fn to_json(&self, options: &FormatOptions) -> serde_json::Value {
+        use serde_json::Value as Json;
+
+        match self {
+            Value::Null => Json::Null,
+            Value::Bool(b) => Json::Bool(*b),
+            Value::Int64(i) => Json::from(*i),
+            Value::Float64(f) => serde_json::Number::from_f64(*f)
+                .map(Json::Number)
+                .unwrap_or_else(|| Json::String(format_float(*f))),
+            Value::Geography(wkt) if options.geography == GeographyFormat::GeoJson => {
+                crate::wkt::to_geojson(wkt).unwrap_or_else(|| Json::String(wkt.clone()))
+            }
+            Value::Json(json) => json.clone(),
+            Value::Range(start, end) => serde_json::json!({
+                "start": start.to_json(options),
+                "end": end.to_json(options),
+            }),
+            Value::Array(items) => items.iter().map(|i| i.to_json(options)).collect(),
+            Value::Struct(fields) => Json::Object(
+                fields
+                    .iter()
+                    .map(|(name, v)| (name.clone(), v.to_json(options)))
+                    .collect(),
+            ),
+            _ => Json::String(self.to_text(options)),
+        }
+    }
+}
+
+impl Interval {
+    /// parses the canonical `Y-M D H:M:S[.F]` format, e.g. `1-2 3 4:5:6.789`
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split_whitespace();
+        let (year_month, days, time) = (parts.next()?, parts.next()?, parts.next()?);
+
+        let (negative, year_month) = sign(year_month);
+        let (years, months) = year_month.split_once('-')?;
+        let months = years.parse::<i64>().ok()? * 12 + months.parse::<i64>().ok()?;
+
+        let (time_negative, time) = sign(time);
+        let mut time = time.splitn(3, ':');
+        let (hours, minutes, seconds) = (time.next()?, time.next()?, time.next()?);
+        let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+        let fraction = format!("{:0<6}", fraction);
+        let micros = hours.parse::<i64>().ok()? * 3_600_000_000
+            + minutes.parse::<i64>().ok()? * 60_000_000
+            + seconds.parse::<i64>().ok()? * 1_000_000
+            + fraction.get(..6)?.parse::<i64>().ok()?;
+
+        Some(Self {
+            months: if negative { -months } else { months },
+            days: days.parse().ok()?,
+            micros: if time_negative { -micros } else { micros },
+        })
+    }
+}
+
+/// renders as an ISO 8601 duration, e.g. `P1Y2M3DT4H5M6.789S`
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if *self == Interval::default() {
+            return write!(f, "PT0S");
+        }
+
+        write!(f, "P")?;
+        let (years, months) = (self.months / 12, self.months % 12);
+        for (value, unit) in [(years, 'Y'), (months, 'M'), (self.days, 'D')] {
+            if value != 0 {
+                write!(f, "{}{}", value, unit)?;
+            }
+        }
+
+        if self.micros == 0 {
+            return Ok(());
+        }
+
+        write!(f, "T")?;
+        let sign = if self.micros < 0 { "-" } else { "" };
+        let micros = self.micros.abs();
+        let (hours, minutes) = (micros / 3_600_000_000, micros / 60_000_000 % 60);
+        let (seconds, fraction) = (micros / 1_000_000 % 60, micros % 1_000_000);
+
+        if hours != 0 {
+            write!(f, "{}{}H", sign, hours)?;
+        }
+        if minutes != 0 {
+            write!(f, "{}{}M", sign, minutes)?;
+        }
+        match (seconds, fraction) {
+            (0, 0) => Ok(()),
+            (s, 0) => write!(f, "{}{}S", sign, s),
+            (s, fraction) => {
+                let fraction = format!("{:06}", fraction);
+                write!(f, "{}{}.{}S", sign, s, fraction.trim_end_matches('0'))
+            }
+        }
+    }
+}
+
+fn sign(s: &str) -> (bool, &str) {
+    match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    }
+}
+
+fn parse_float(s: &str) -> Option<f64> {
+    match s {
+        "NaN" => Some(f64::NAN),
+        "Infinity" => Some(f64::INFINITY),
+        "-Infinity" => Some(f64::NEG_INFINITY),
+        _ => s.parse().ok(),
+    }
+}
+
+fn format_float(f: f64) -> String {
+    match f {
+        f if f.is_nan() => "NaN".to_string(),
+        f64::INFINITY => "Infinity".to_string(),
+        f64::NEG_INFINITY => "-Infinity".to_string(),
+        f => f.to_string(),
+    }
+}
+
+/// timestamps are either float seconds (`1.7E9`) or, when `use_int64_timestamp` is set,
+/// integer microseconds since the epoch
+///
+/// float seconds are parsed from their decimal text so no precision is lost
+pub(crate) fn timestamp_micros(s: &str) -> Option<i64> {
+    if !s.contains(['.', 'E', 'e']) {
+        return s.parse().ok();
+    }
+
+    let (mantissa, exponent) = match s.split_once(['E', 'e']) {
+        Some((m, e)) => (m, e.parse::<i32>().ok()?),
+        None => (s, 0),
+    };
+
+    let (negative, mantissa) = sign(mantissa);
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits: i128 = format!("{}{}", whole, fraction).parse().ok()?;
+
+    // shift the decimal point so the digits are in microseconds
+    let shift = exponent + 6 - fraction.len() as i32;
+    let micros = if shift >= 0 {
+        digits.checked_mul(10_i128.checked_pow(shift as u32)?)?
+    } else {
+        digits / 10_i128.checked_pow(shift.unsigned_abs())?
+    };
+
+    i64::try_from(if negative { -micros } else { micros }).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{timestamp_micros, FormatOptions, GeographyFormat, Interval, Value};
+    use crate::query::response::TableFieldSchema;
+
+    fn field(json: serde_json::Value) -> TableFieldSchema {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn parses_timestamps_without_losing_precision() {
+        assert_eq!(
+            timestamp_micros("1.696118400123456E9"),
+            Some(1_696_118_400_123_456)
+        );
+        assert_eq!(
+            timestamp_micros("1696118400123456"),
+            Some(1_696_118_400_123_456)
+        );
+        assert_eq!(timestamp_micros("-1.5E0"), Some(-1_500_000));
+    }
+
+    #[test]
+    fn renders_timestamps_as_rfc3339() {
+        let field =
+            field(serde_json::json!({ "name": "t", "type": "TIMESTAMP", "mode": "NULLABLE" }));
+        let value = Value::decode(&field, &serde_json::json!("1.6961184001E9"));
+
+        assert_eq!(
+            value.to_text(&FormatOptions::default()),
+            "2023-10-01T00:00:00.100Z"
+        );
+
+        let options = FormatOptions {
+            timezone: Some(chrono_tz::America::New_York),
+            ..Default::default()
+        };
+        assert_eq!(value.to_text(&options), "2023-09-30T20:00:00.100-04:00");
+    }
+
+    #[test]
+    fn keeps_numeric_precision() {
+        let field =
+            field(serde_json::json!({ "name": "n", "type": "BIGNUMERIC", "mode": "NULLABLE" }));
+        let text = "123456789012345678901234567890.123456789012345678901234567890";
+        let value = Value::decode(&field, &serde_json::json!(text));
+
+        assert_eq!(value.to_text(&FormatOptions::default()), text);
+        assert_eq!(
+            value.to_json(&FormatOptions::default()),
+            serde_json::json!(text)
+        );
+    }
+
+    #[test]
+    fn embeds_json_and_geography() {
+        let record = field(
+            serde_json::json!({ "name": "r", "type": "RECORD", "mode": "NULLABLE", "fields": [
+                { "name": "j", "type": "JSON", "mode": "NULLABLE" },
+                { "name": "g", "type": "GEOGRAPHY", "mode": "NULLABLE" }
+            ]}),
+        );
+        let value = Value::decode(
+            &record,
+            &serde_json::json!({ "f": [{ "v": "{\"a\":1}" }, { "v": "POINT(1 2)" }] }),
+        );
+
+        let options = FormatOptions {
+            geography: GeographyFormat::GeoJson,
+            ..Default::default()
+        };
+        assert_eq!(
+            value.to_json(&options),
+            serde_json::json!({
+                "j": { "a": 1 },
+                "g": { "type": "Point", "coordinates": [1.0, 2.0] }
+            })
+        );
+    }
+
+    #[test]
+    fn formats_intervals_and_ranges() {
+        assert_eq!(
+            Interval::parse("1-2 3 4:5:6.789").unwrap().to_string(),
+            "P1Y2M3DT4H5M6.789S"
+        );
+        assert_eq!(
+            Interval::parse("-0-1 0 -0:0:30").unwrap().to_string(),
+            "P-1MT-30S"
+        );
+        assert_eq!(Interval::parse("0-0 0 0:0:0").unwrap().to_string(), "PT0S");
+
+        let range = field(serde_json::json!({
+            "name": "r", "type": "RANGE", "mode": "NULLABLE", "rangeElementType": { "type": "DATE" }
+        }));
+        let value = Value::decode(&range, &serde_json::json!("[2024-01-01, UNBOUNDED)"));
+        assert_eq!(
+            value.to_text(&FormatOptions::default()),
+            "[2024-01-01, UNBOUNDED)"
+        );
+        assert_eq!(
+            value.to_json(&FormatOptions::default()),
+            serde_json::json!({ "start": "2024-01-01", "end": null })
+        );
+    }
+}
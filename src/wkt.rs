@@ -0,0 +1,184 @@
+//! conversion of well known text geographies into GeoJSON
+//!
+//! <https://en.wikipedia.org/wiki/Well-known_text_representation_of_geometry>
+
+use serde_json::Value;
+
+/// returns `None` when the text is not valid WKT
+pub fn to_geojson(wkt: &str) -> Option<Value> {
+    let mut tokens = Tokens::new(wkt);
+    let geometry = geometry(&mut tokens)?;
+    tokens.next().is_none().then_some(geometry)
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Word(&'a str),
+    Open,
+    Close,
+    Comma,
+}
+
+struct Tokens<'a> {
+    text: &'a str,
+    peeked: Option<Token<'a>>,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, peeked: None }
+    }
+
+    fn peek(&mut self) -> Option<&Token<'a>> {
+        if self.peeked.is_none() {
+            self.peeked = self.read();
+        }
+        self.peeked.as_ref()
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        self.peeked.take().or_else(|| self.read())
+    }
+
+    fn expect(&mut self, token: Token) -> Option<()> {
+        (self.next()? == token).then_some(())
+    }
+
+    fn read(&mut self) -> Option<Token<'a>> {
+        self.text = self.text.trim_start();
+        let mut chars = self.text.chars();
+        let token = match chars.next()? {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            _ => {
+                let end = self
+                    .text
+                    .find(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | ','))
+                    .unwrap_or(self.text.len());
+                let (word, rest) = self.text.split_at(end);
+                self.text = rest;
+                return Some(Token::Word(word));
+            }
+        };
+        self.text = chars.as_str();
+        Some(token)
+    }
+}
+
+fn geometry(tokens: &mut Tokens) -> Option<Value> {
+    let Token::Word(kind) = tokens.next()? else {
+        return None;
+    };
+    let kind = kind.to_ascii_uppercase();
+
+    let name = match kind.as_str() {
+        "POINT" => "Point",
+        "LINESTRING" => "LineString",
+        "POLYGON" => "Polygon",
+        "MULTIPOINT" => "MultiPoint",
+        "MULTILINESTRING" => "MultiLineString",
+        "MULTIPOLYGON" => "MultiPolygon",
+        "GEOMETRYCOLLECTION" => "GeometryCollection",
+        _ => return None,
+    };
+
+    let empty = matches!(tokens.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case("EMPTY"));
+    if empty {
+        tokens.next();
+    }
+
+    if name == "GeometryCollection" {
+        let mut geometries = Vec::new();
+        if !empty {
+            tokens.expect(Token::Open)?;
+            loop {
+                geometries.push(geometry(tokens)?);
+                match tokens.next()? {
+                    Token::Comma => continue,
+                    Token::Close => break,
+                    _ => return None,
+                }
+            }
+        }
+        return Some(serde_json::json!({ "type": name, "geometries": geometries }));
+    }
+
+    let coordinates = match (empty, name) {
+        (true, _) => Value::Array(Vec::new()),
+        // a point is a single position rather than a list of them
+        (false, "Point") => coordinates(tokens)?.as_array()?.first()?.clone(),
+        // multipoints may omit the parentheses around each point
+        (false, "MultiPoint") => coordinates(tokens)?
+            .as_array()?
+            .iter()
+            .map(|p| match p.as_array()?.first()? {
+                Value::Array(point) => Some(Value::Array(point.clone())),
+                _ => Some(p.clone()),
+            })
+            .collect::<Option<Value>>()?,
+        (false, _) => coordinates(tokens)?,
+    };
+
+    Some(serde_json::json!({ "type": name, "coordinates": coordinates }))
+}
+
+/// a parenthesized list of positions or of nested lists
+fn coordinates(tokens: &mut Tokens) -> Option<Value> {
+    tokens.expect(Token::Open)?;
+    let mut items = Vec::new();
+
+    loop {
+        let item = match tokens.peek()? {
+            Token::Open => coordinates(tokens)?,
+            _ => position(tokens)?,
+        };
+        items.push(item);
+
+        match tokens.next()? {
+            Token::Comma => continue,
+            Token::Close => break,
+            _ => return None,
+        }
+    }
+
+    Some(Value::Array(items))
+}
+
+fn position(tokens: &mut Tokens) -> Option<Value> {
+    let mut numbers = Vec::new();
+    while let Some(Token::Word(word)) = tokens.peek() {
+        numbers.push(word.parse::<f64>().ok()?);
+        tokens.next();
+    }
+    (!numbers.is_empty()).then(|| numbers.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::to_geojson;
+
+    #[test]
+    fn converts_geometries() {
+        assert_eq!(
+            to_geojson("POINT(-122.35 47.62)").unwrap(),
+            serde_json::json!({ "type": "Point", "coordinates": [-122.35, 47.62] })
+        );
+        assert_eq!(
+            to_geojson("POLYGON((0 0, 1 0, 1 1, 0 0))").unwrap(),
+            serde_json::json!({ "type": "Polygon", "coordinates": [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0]]] })
+        );
+        assert_eq!(
+            to_geojson("MULTIPOINT(1 2, (3 4))").unwrap(),
+            serde_json::json!({ "type": "MultiPoint", "coordinates": [[1.0, 2.0], [3.0, 4.0]] })
+        );
+        assert_eq!(
+            to_geojson("GEOMETRYCOLLECTION(POINT(1 2), LINESTRING(0 0, 1 1))").unwrap(),
+            serde_json::json!({ "type": "GeometryCollection", "geometries": [
+                { "type": "Point", "coordinates": [1.0, 2.0] },
+                { "type": "LineString", "coordinates": [[0.0, 0.0], [1.0, 1.0]] }
+            ]})
+        );
+        assert!(to_geojson("POINT(1 2").is_none());
+    }
+}